@@ -0,0 +1,176 @@
+//! Helpers to encode foreign types with `minicbor`, to be used with `#[cbor(with = "...")]`.
+
+pub mod principal {
+    use candid::Principal;
+    use minicbor::decode::{Decoder, Error};
+    use minicbor::encode::{Encoder, Write};
+
+    pub fn decode<Ctx>(d: &mut Decoder<'_>, _ctx: &mut Ctx) -> Result<Principal, Error> {
+        let bytes = d.bytes()?;
+        Principal::try_from_slice(bytes).map_err(|e| Error::message(e.to_string()))
+    }
+
+    pub fn encode<Ctx, W: Write>(
+        v: &Principal,
+        e: &mut Encoder<W>,
+        _ctx: &mut Ctx,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        e.bytes(v.as_slice())?;
+        Ok(())
+    }
+
+    pub mod option {
+        use candid::Principal;
+        use minicbor::data::Type;
+        use minicbor::decode::{Decoder, Error};
+        use minicbor::encode::{Encoder, Write};
+
+        pub fn decode<Ctx>(d: &mut Decoder<'_>, ctx: &mut Ctx) -> Result<Option<Principal>, Error> {
+            if d.datatype()? == Type::Null {
+                d.skip()?;
+                return Ok(None);
+            }
+            super::decode(d, ctx).map(Some)
+        }
+
+        pub fn encode<Ctx, W: Write>(
+            v: &Option<Principal>,
+            e: &mut Encoder<W>,
+            ctx: &mut Ctx,
+        ) -> Result<(), minicbor::encode::Error<W::Error>> {
+            match v {
+                Some(principal) => super::encode(principal, e, ctx),
+                None => {
+                    e.null()?;
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+pub mod u256 {
+    use minicbor::data::{Tag, Type};
+    use minicbor::decode::{Decoder, Error};
+    use minicbor::encode::{Encoder, Write};
+
+    /// Values that fit into a `u64` are encoded as CBOR integers,
+    /// larger values as positive bignums (tag 2) without leading zero bytes.
+    pub fn decode<Ctx>(d: &mut Decoder<'_>, _ctx: &mut Ctx) -> Result<ethnum::u256, Error> {
+        match d.datatype()? {
+            Type::U8 | Type::U16 | Type::U32 | Type::U64 => Ok(ethnum::u256::from(d.u64()?)),
+            Type::Tag => {
+                let tag = d.tag()?;
+                if tag != Tag::PosBignum {
+                    return Err(Error::message(format!(
+                        "expected a positive bignum tag, got {:?}",
+                        tag
+                    )));
+                }
+                let bytes = d.bytes()?;
+                if bytes.len() > 32 {
+                    return Err(Error::message(format!(
+                        "expected at most 32 bytes, got {}",
+                        bytes.len()
+                    )));
+                }
+                let mut be_bytes = [0u8; 32];
+                be_bytes[32 - bytes.len()..].copy_from_slice(bytes);
+                Ok(ethnum::u256::from_be_bytes(be_bytes))
+            }
+            ty => Err(Error::type_mismatch(ty).with_message("expected an integer or a bignum")),
+        }
+    }
+
+    pub fn encode<Ctx, W: Write>(
+        v: &ethnum::u256,
+        e: &mut Encoder<W>,
+        _ctx: &mut Ctx,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        if *v <= ethnum::u256::from(u64::MAX) {
+            e.u64(v.as_u64())?;
+        } else {
+            let be_bytes = v.to_be_bytes();
+            let leading_zeroes = be_bytes.iter().take_while(|b| **b == 0).count();
+            e.tag(Tag::PosBignum)?.bytes(&be_bytes[leading_zeroes..])?;
+        }
+        Ok(())
+    }
+}
+
+pub mod id {
+    use minicbor::decode::{Decoder, Error};
+    use minicbor::encode::{Encoder, Write};
+    use phantom_newtype::Id;
+
+    pub fn decode<Ctx, Tag>(d: &mut Decoder<'_>, _ctx: &mut Ctx) -> Result<Id<Tag, u64>, Error> {
+        Ok(Id::new(d.u64()?))
+    }
+
+    pub fn encode<Ctx, W: Write, Tag>(
+        v: &Id<Tag, u64>,
+        e: &mut Encoder<W>,
+        _ctx: &mut Ctx,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        e.u64(v.get())?;
+        Ok(())
+    }
+}
+
+pub mod nat {
+    use candid::Nat;
+    use minicbor::decode::{Decoder, Error};
+    use minicbor::encode::{Encoder, Write};
+    use num_bigint::BigUint;
+
+    pub fn decode<Ctx>(d: &mut Decoder<'_>, ctx: &mut Ctx) -> Result<Nat, Error> {
+        let value = super::u256::decode(d, ctx)?;
+        Ok(Nat::from(BigUint::from_bytes_be(&value.to_be_bytes())))
+    }
+
+    pub fn encode<Ctx, W: Write>(
+        v: &Nat,
+        e: &mut Encoder<W>,
+        ctx: &mut Ctx,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        let bytes = v.0.to_bytes_be();
+        if bytes.len() > 32 {
+            return Err(minicbor::encode::Error::message(format!(
+                "Nat does not fit in a U256: {}",
+                v
+            )));
+        }
+        let mut be_bytes = [0u8; 32];
+        be_bytes[32 - bytes.len()..].copy_from_slice(&bytes);
+        super::u256::encode(&ethnum::u256::from_be_bytes(be_bytes), e, ctx)
+    }
+
+    pub mod option {
+        use candid::Nat;
+        use minicbor::data::Type;
+        use minicbor::decode::{Decoder, Error};
+        use minicbor::encode::{Encoder, Write};
+
+        pub fn decode<Ctx>(d: &mut Decoder<'_>, ctx: &mut Ctx) -> Result<Option<Nat>, Error> {
+            if d.datatype()? == Type::Null {
+                d.skip()?;
+                return Ok(None);
+            }
+            super::decode(d, ctx).map(Some)
+        }
+
+        pub fn encode<Ctx, W: Write>(
+            v: &Option<Nat>,
+            e: &mut Encoder<W>,
+            ctx: &mut Ctx,
+        ) -> Result<(), minicbor::encode::Error<W::Error>> {
+            match v {
+                Some(nat) => super::encode(nat, e, ctx),
+                None => {
+                    e.null()?;
+                    Ok(())
+                }
+            }
+        }
+    }
+}
@@ -89,6 +89,10 @@ impl<Unit> CheckedAmountOf<Unit> {
         }
     }
 
+    pub fn checked_div_floor<T: Into<ethnum::u256>>(self, rhs: T) -> Option<Self> {
+        self.0.checked_div(rhs.into()).map(Self::from_inner)
+    }
+
     pub fn div_by_two(self) -> Self {
         Self::from_inner(self.0 >> 1)
     }
@@ -202,6 +206,30 @@ impl<Unit> Ord for CheckedAmountOf<Unit> {
     }
 }
 
+impl<C, Unit> minicbor::Encode<C> for CheckedAmountOf<Unit> {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+        ctx: &mut C,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        crate::cbor::u256::encode(&self.0, e, ctx)
+    }
+}
+
+impl<'b, C, Unit> minicbor::Decode<'b, C> for CheckedAmountOf<Unit> {
+    fn decode(d: &mut minicbor::Decoder<'b>, ctx: &mut C) -> Result<Self, minicbor::decode::Error> {
+        crate::cbor::u256::decode(d, ctx).map(Self::from_inner)
+    }
+}
+
+impl<Unit> rlp::Encodable for CheckedAmountOf<Unit> {
+    fn rlp_append(&self, s: &mut rlp::RlpStream) {
+        // RLP encodes integers as big-endian bytes without leading zeroes.
+        let leading_empty_bytes = self.0.leading_zeros() as usize / 8;
+        s.append(&&self.0.to_be_bytes()[leading_empty_bytes..]);
+    }
+}
+
 // Derived serde `impl Serialize` produces an extra `unit` value for
 // phantom data, e.g. `AmountOf::<Meters>::from(10)` is serialized
 // into json as `[10, null]` by default.
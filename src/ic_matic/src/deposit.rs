@@ -0,0 +1,678 @@
+use candid::Nat;
+use ic_canister_log::log;
+use icrc_ledger_client_cdk::{CdkRuntime, ICRC1Client};
+use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc1::transfer::TransferArg;
+use num_traits::ToPrimitive;
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::endpoints::CandidBlockTag;
+use crate::events_utils::{
+    EventSource, EventSourceError, ReceivedEventError, ReceivedPolygonEvent, TokensLockedLog,
+    NATIVE_TOKEN_TOPIC, TOKENS_LOCKED_EVENT_TOPIC,
+};
+use crate::evm_rpc_canister::GetLogsArgs;
+use crate::guard::TimerGuard;
+use crate::log_types::{address::Address, hash::Hash};
+use crate::logs::{DEBUG, INFO};
+use crate::memo::MintMemo;
+use crate::numeric::{BlockNumber, LedgerMintIndex};
+use crate::rpc_client::{
+    RpcClient, RpcClientError, GET_LOGS_RESPONSE_SIZE_ESTIMATE, MAX_RESPONSE_SIZE_ESTIMATE,
+};
+use crate::rpc_providers::RpcProvider;
+use crate::state::audit::process_event;
+use crate::state::event::EventType;
+use crate::state::{mutate_state, read_state, TaskType};
+
+/// Maximum number of blocks requested in a single `eth_getLogs` call.
+const MAX_BLOCK_SPREAD: u16 = 500;
+
+/// Maximum number of `eth_getLogs` calls failing with a too large response in a single run,
+/// enough to reach both the largest response size estimate and a single block.
+const MAX_FAILED_ATTEMPTS_PER_RUN: u32 = 20;
+
+/// Delay before the second attempt to scrape a skipped block, doubled after every failure.
+const SKIPPED_BLOCK_RETRY_INITIAL_DELAY: Duration = Duration::from_secs(10 * 60);
+const SKIPPED_BLOCK_RETRY_MAX_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The kinds of deposits in the helper contract logs, each scraped with its own cursor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DepositKind {
+    /// Deposits of MATIC, minted as icMATIC.
+    Native,
+    /// Deposits of the supported ERC-20 tokens, minted as icERC20.
+    Erc20,
+}
+
+impl DepositKind {
+    fn last_scraped_block_number(&self) -> BlockNumber {
+        match self {
+            DepositKind::Native => read_state(|s| s.last_scraped_block_number),
+            DepositKind::Erc20 => read_state(|s| s.last_erc20_scraped_block_number),
+        }
+    }
+
+    /// The `token` topics of the `TokensLocked` events of this kind.
+    fn token_topics(&self) -> Vec<String> {
+        match self {
+            DepositKind::Native => vec![NATIVE_TOKEN_TOPIC.to_string()],
+            DepositKind::Erc20 => read_state(|s| {
+                s.erc20_tokens
+                    .keys()
+                    .map(|address| format!("0x{}", hex::encode(<[u8; 32]>::from(address))))
+                    .collect()
+            }),
+        }
+    }
+
+    fn synced_to_block(&self, block_number: BlockNumber) -> EventType {
+        match self {
+            DepositKind::Native => EventType::SyncedToBlock { block_number },
+            DepositKind::Erc20 => EventType::SyncedErc20ToBlock { block_number },
+        }
+    }
+
+    fn minted(&self, event_source: EventSource, mint_block_index: LedgerMintIndex) -> EventType {
+        match self {
+            DepositKind::Native => EventType::MintedIcMatic {
+                event_source,
+                mint_block_index,
+            },
+            DepositKind::Erc20 => EventType::MintedIcErc20 {
+                event_source,
+                mint_block_index,
+            },
+        }
+    }
+
+    fn matches(&self, token_contract_address: &Address) -> bool {
+        (*token_contract_address == Address::ZERO) == (*self == DepositKind::Native)
+    }
+}
+
+/// Scrapes the helper contract logs from the last scraped block up to the
+/// block at the configured height (`finalized` by default), in chunks of at most
+/// [`MAX_BLOCK_SPREAD`] blocks, then mints icMATIC and icERC20 for the accepted deposits.
+pub async fn scrape_eth_logs() {
+    let _guard = match TimerGuard::new(TaskType::ScrapEthLogs) {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+    let contract_address = match read_state(|s| s.eth_helper_contract_address) {
+        Some(address) => address,
+        None => {
+            log!(
+                DEBUG,
+                "[scrape_eth_logs]: skipping scraping logs: no helper contract address"
+            );
+            return;
+        }
+    };
+    match update_last_observed_block_number().await {
+        Some(last_block_number) => {
+            for kind in [DepositKind::Native, DepositKind::Erc20] {
+                scrape_until_block(contract_address, last_block_number, kind).await;
+            }
+        }
+        None => {
+            log!(
+                DEBUG,
+                "[scrape_eth_logs]: skipping scraping logs: no last observed block number"
+            );
+        }
+    }
+    mint().await;
+    mint_erc20().await;
+}
+
+/// Scrapes the logs of the given kind up to `last_block_number`.
+///
+/// When the response is too large, the request is retried with a doubled response
+/// size estimate and, once the estimate is at its maximum, with a halved block range.
+/// A range whose logs still cannot be fetched at the last attempt of the run, or a single
+/// block, is recorded as skipped. Any other error stops the run without moving the cursor.
+async fn scrape_until_block(
+    contract_address: Address,
+    last_block_number: BlockNumber,
+    kind: DepositKind,
+) {
+    let mut last_scraped_block_number = kind.last_scraped_block_number();
+    let providers = read_state(|s| s.rpc_providers.clone());
+    let mut block_spread = MAX_BLOCK_SPREAD;
+    let mut response_size_estimate = GET_LOGS_RESPONSE_SIZE_ESTIMATE;
+    let mut failed_attempts = 0;
+    while last_scraped_block_number < last_block_number {
+        let from_block = last_scraped_block_number
+            .checked_increment()
+            .expect("BUG: last scraped block number is smaller than the last observed one");
+        let to_block = min(
+            from_block
+                .checked_add(BlockNumber::from(block_spread - 1))
+                .unwrap_or(BlockNumber::MAX),
+            last_block_number,
+        );
+        match scrape_block_range(
+            contract_address,
+            from_block,
+            to_block,
+            kind,
+            providers.clone(),
+            response_size_estimate,
+        )
+        .await
+        {
+            Ok(()) => {
+                mutate_state(|s| process_event(s, kind.synced_to_block(to_block)));
+                last_scraped_block_number = to_block;
+                block_spread = MAX_BLOCK_SPREAD;
+                response_size_estimate = GET_LOGS_RESPONSE_SIZE_ESTIMATE;
+            }
+            Err(e) if e.is_response_too_large() => {
+                failed_attempts += 1;
+                log!(
+                    INFO,
+                    "[scrape_eth_logs]: failed to get {kind:?} logs from block {from_block} to {to_block} with a response size estimate of {response_size_estimate} bytes: {e:?}"
+                );
+                let last_attempt = failed_attempts >= MAX_FAILED_ATTEMPTS_PER_RUN;
+                if response_size_estimate < MAX_RESPONSE_SIZE_ESTIMATE && !last_attempt {
+                    response_size_estimate =
+                        min(response_size_estimate * 2, MAX_RESPONSE_SIZE_ESTIMATE);
+                } else if from_block < to_block && !last_attempt {
+                    block_spread = max(block_spread / 2, 1);
+                } else {
+                    log!(
+                        INFO,
+                        "[scrape_eth_logs]: skipping blocks {from_block} to {to_block} whose {kind:?} logs cannot be fetched"
+                    );
+                    mutate_state(|s| {
+                        let mut block = from_block;
+                        loop {
+                            if !s.skipped_blocks.contains(&block) {
+                                process_event(s, EventType::SkippedBlock(block));
+                            }
+                            if block == to_block {
+                                break;
+                            }
+                            block = block
+                                .checked_increment()
+                                .expect("BUG: block range is not bounded");
+                        }
+                        process_event(s, kind.synced_to_block(to_block));
+                    });
+                    last_scraped_block_number = to_block;
+                    block_spread = MAX_BLOCK_SPREAD;
+                    response_size_estimate = GET_LOGS_RESPONSE_SIZE_ESTIMATE;
+                }
+                if last_attempt {
+                    return;
+                }
+            }
+            Err(e) => {
+                log!(
+                    INFO,
+                    "[scrape_eth_logs]: failed to get {kind:?} logs from block {from_block} to {to_block}: {e:?}"
+                );
+                return;
+            }
+        }
+    }
+}
+
+/// Retries scraping the skipped blocks whose backoff delay elapsed, with the largest
+/// response size estimate. Each attempt leaves out a different provider, so that
+/// a single faulty provider cannot prevent a block from being scraped.
+/// The delay before the next attempt doubles after every failure.
+pub async fn retry_skipped_blocks() {
+    let _guard = match TimerGuard::new(TaskType::RetrySkippedBlocks) {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+    let contract_address = match read_state(|s| s.eth_helper_contract_address) {
+        Some(address) => address,
+        None => return,
+    };
+    let now = ic_cdk::api::time();
+    let blocks: Vec<_> = read_state(|s| {
+        s.skipped_blocks
+            .iter()
+            .map(|block_number| {
+                let retry = s
+                    .skipped_block_retries
+                    .get(block_number)
+                    .copied()
+                    .unwrap_or_default();
+                (*block_number, retry)
+            })
+            .filter(|(_, retry)| retry.next_retry_at <= now)
+            .collect()
+    });
+
+    for (block_number, retry) in blocks {
+        let providers = retry_providers(retry.attempts);
+        let mut result = Ok(());
+        for kind in [DepositKind::Native, DepositKind::Erc20] {
+            result = scrape_block_range(
+                contract_address,
+                block_number,
+                block_number,
+                kind,
+                providers.clone(),
+                MAX_RESPONSE_SIZE_ESTIMATE,
+            )
+            .await;
+            if result.is_err() {
+                break;
+            }
+        }
+        match result {
+            Ok(()) => {
+                log!(
+                    INFO,
+                    "[retry_skipped_blocks]: scraped skipped block {block_number}"
+                );
+                mutate_state(|s| {
+                    // The block may have been abandoned in the meantime.
+                    if s.skipped_blocks.contains(&block_number) {
+                        process_event(s, EventType::ScrapedSkippedBlock { block_number });
+                    }
+                });
+            }
+            Err(e) => {
+                let attempts = retry.attempts.saturating_add(1);
+                let delay = SKIPPED_BLOCK_RETRY_INITIAL_DELAY
+                    .saturating_mul(2_u32.saturating_pow(attempts.min(31) - 1))
+                    .min(SKIPPED_BLOCK_RETRY_MAX_DELAY);
+                log!(
+                    INFO,
+                    "[retry_skipped_blocks]: attempt {attempts} to scrape block {block_number} failed, retrying in {delay:?}: {e:?}"
+                );
+                mutate_state(|s| {
+                    // The block may have been abandoned in the meantime.
+                    if s.skipped_blocks.contains(&block_number) {
+                        process_event(
+                            s,
+                            EventType::FailedSkippedBlockRetry {
+                                block_number,
+                                next_retry_at: now.saturating_add(delay.as_nanos() as u64),
+                            },
+                        );
+                    }
+                });
+            }
+        }
+    }
+    mint().await;
+    mint_erc20().await;
+}
+
+/// The providers queried by the given attempt to scrape a skipped block:
+/// all configured ones but one, which changes with every attempt.
+fn retry_providers(attempt: u32) -> Vec<RpcProvider> {
+    let mut providers = read_state(|s| s.rpc_providers.clone());
+    if providers.len() > 1 {
+        providers.remove(attempt as usize % providers.len());
+    }
+    providers
+}
+
+/// Mints icMATIC for every MATIC deposit in `events_to_mint`.
+///
+/// A deposit for which the ledger call failed is retried later.
+/// A deposit for which the outcome of the ledger call is unknown,
+/// because the canister trapped while processing the ledger response,
+/// is quarantined so that it can never be minted twice.
+pub async fn mint() {
+    let _guard = match TimerGuard::new(TaskType::Mint) {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+    if mint_deposits(DepositKind::Native).await > 0 {
+        ic_cdk_timers::set_timer(crate::MINT_RETRY_DELAY, || ic_cdk::spawn(mint()));
+    }
+}
+
+/// Mints icERC20 for every ERC-20 deposit in `events_to_mint`, like [`mint`].
+pub async fn mint_erc20() {
+    let _guard = match TimerGuard::new(TaskType::MintCkErc20) {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+    if mint_deposits(DepositKind::Erc20).await > 0 {
+        ic_cdk_timers::set_timer(crate::MINT_RETRY_DELAY, || ic_cdk::spawn(mint_erc20()));
+    }
+}
+
+/// Mints the deposits of the given kind on the ledger of their token.
+/// Returns the number of deposits that failed to be minted.
+async fn mint_deposits(kind: DepositKind) -> usize {
+    let events: Vec<_> = read_state(|s| {
+        s.events_to_mint
+            .iter()
+            .filter(|(_, event)| kind.matches(&event.token_contract_address))
+            .map(|(source, event)| (*source, *event))
+            .collect()
+    });
+    let mut error_count = 0;
+
+    for (event_source, event) in events {
+        let (ledger_canister_id, token_symbol) = match read_state(|s| {
+            s.ledger_of(&event.token_contract_address)
+        }) {
+            Some(ledger) => ledger,
+            None => {
+                log!(
+                    INFO,
+                    "Cannot mint a deposit of an unsupported token: {event:?}"
+                );
+                mutate_state(|s| process_event(s, EventType::UnsupportedDeposit { event_source }));
+                continue;
+            }
+        };
+        let client = ICRC1Client {
+            runtime: CdkRuntime,
+            ledger_canister_id,
+        };
+        // Ensure that even if we were to panic in the callback, after having contacted the ledger to mint the tokens,
+        // this event will not be processed again.
+        let prevent_double_minting_guard = QuarantineOnDrop::new(event_source);
+        let block_index = match client
+            .transfer(TransferArg {
+                from_subaccount: None,
+                to: Account {
+                    owner: event.principal,
+                    subaccount: None,
+                },
+                fee: None,
+                created_at_time: None,
+                memo: Some(MintMemo::from(&event).into()),
+                amount: Nat::from(event.value),
+            })
+            .await
+        {
+            // The tokens are minted: the deposit must be recorded as such even if the
+            // block index is out of range.
+            Ok(Ok(block_index)) => block_index.0.to_u64().unwrap_or_else(|| {
+                log!(
+                    INFO,
+                    "[ALERT] minted {token_symbol} for {event:?} in block {block_index} beyond u64"
+                );
+                u64::MAX
+            }),
+            Ok(Err(err)) => {
+                log!(INFO, "Failed to mint {token_symbol}: {event:?} {err}");
+                error_count += 1;
+                prevent_double_minting_guard.disarm();
+                continue;
+            }
+            Err(err) => {
+                log!(
+                    INFO,
+                    "Failed to send a message to the ledger ({ledger_canister_id}): {err:?}"
+                );
+                error_count += 1;
+                prevent_double_minting_guard.disarm();
+                continue;
+            }
+        };
+        mutate_state(|s| {
+            process_event(
+                s,
+                kind.minted(event_source, LedgerMintIndex::new(block_index)),
+            )
+        });
+        log!(
+            INFO,
+            "Minted {} {token_symbol} to {} in block {block_index}",
+            event.value,
+            event.principal
+        );
+        prevent_double_minting_guard.disarm();
+    }
+
+    if error_count > 0 {
+        log!(
+            INFO,
+            "Failed to mint {error_count} {kind:?} events, rescheduling the minting"
+        );
+    }
+    error_count
+}
+
+/// Quarantines the deposit when dropped, unless disarmed.
+/// Destructors of pending futures run when the canister traps in a callback.
+struct QuarantineOnDrop {
+    event_source: Option<EventSource>,
+}
+
+impl QuarantineOnDrop {
+    fn new(event_source: EventSource) -> Self {
+        Self {
+            event_source: Some(event_source),
+        }
+    }
+
+    fn disarm(mut self) {
+        self.event_source = None;
+    }
+}
+
+impl Drop for QuarantineOnDrop {
+    fn drop(&mut self) {
+        if let Some(event_source) = self.event_source.take() {
+            mutate_state(|s| process_event(s, EventType::QuarantinedDeposit { event_source }));
+        }
+    }
+}
+
+/// Fetches the last block whose logs can be scraped without risking a chain reorganization:
+/// the `Finalized` or `Safe` block, or the `Latest` one minus the required confirmations.
+async fn update_last_observed_block_number() -> Option<BlockNumber> {
+    let (block_height, confirmations) =
+        read_state(|s| (s.ethereum_block_height, s.latest_block_confirmations));
+    match RpcClient.get_block_by_number(block_height.into()).await {
+        Ok(block) => {
+            let mut block_number = BlockNumber::from(block.number);
+            if block_height == CandidBlockTag::Latest {
+                block_number = block_number
+                    .checked_sub(BlockNumber::from(confirmations))
+                    .unwrap_or(BlockNumber::ZERO);
+            }
+            mutate_state(|s| s.last_observed_block_number = Some(block_number));
+            Some(block_number)
+        }
+        Err(e) => {
+            log!(
+                INFO,
+                "[update_last_observed_block_number]: failed to get the {block_height:?} block: {e:?}"
+            );
+            read_state(|s| s.last_observed_block_number)
+        }
+    }
+}
+
+/// Handles a deposit of a transaction that was already accepted in another block. The deposit is
+/// never credited twice: an accepted deposit seen again is reported once, and any other deposit
+/// of the transaction is rejected as it may be the same one at another log index.
+fn record_reorged_deposit(log: TokensLockedLog, accepted: ReceivedPolygonEvent) {
+    let block_hash = log.block_hash;
+    log!(
+        INFO,
+        "[ALERT] deposit {log:?} conflicts with {accepted:?} accepted in another block: the chain was reorganized"
+    );
+    mutate_state(|s| {
+        let event_source = log.source();
+        if s.accepted_deposit(&event_source).is_some() {
+            if s.reorged_deposits.get(&event_source) != Some(&block_hash) {
+                process_event(
+                    s,
+                    EventType::DetectedReorg {
+                        event_source,
+                        block_hash,
+                    },
+                );
+            }
+        } else if !s.is_processed(&event_source) {
+            process_event(
+                s,
+                EventType::InvalidDeposit {
+                    event_source,
+                    reason: format!(
+                        "transaction was already deposited in block {}",
+                        accepted.block_number
+                    ),
+                },
+            );
+        }
+    });
+}
+
+/// Records a deposit that will never be minted.
+fn record_invalid_deposit(event_source: EventSource, reason: String) {
+    mutate_state(|s| {
+        process_event(
+            s,
+            EventType::InvalidDeposit {
+                event_source,
+                reason,
+            },
+        )
+    });
+}
+
+/// Records the deposits of the given kind between `from_block` and `to_block`, inclusive.
+async fn scrape_block_range(
+    contract_address: Address,
+    from_block: BlockNumber,
+    to_block: BlockNumber,
+    kind: DepositKind,
+    providers: Vec<RpcProvider>,
+    response_size_estimate: u64,
+) -> Result<(), RpcClientError> {
+    let token_topics = kind.token_topics();
+    // Without any token to look for, there is nothing to scrape.
+    let entries = if token_topics.is_empty() {
+        vec![]
+    } else {
+        RpcClient
+            .get_logs(
+                providers.clone(),
+                GetLogsArgs {
+                    fromBlock: Some(from_block.into()),
+                    toBlock: Some(to_block.into()),
+                    addresses: vec![contract_address.to_string()],
+                    topics: Some(vec![
+                        vec![TOKENS_LOCKED_EVENT_TOPIC.to_string()],
+                        token_topics,
+                    ]),
+                },
+                response_size_estimate,
+            )
+            .await?
+    };
+    // The principal of a deposit is only found in the input of its transaction,
+    // fetched once for all the deposits of the transaction.
+    let mut transaction_inputs: BTreeMap<Hash, Option<Vec<u8>>> = BTreeMap::new();
+    for entry in entries {
+        match TokensLockedLog::try_from(entry) {
+            Ok(log) => {
+                if let Some(accepted) = read_state(|s| {
+                    s.reorged_deposit(log.transaction_hash, log.block_hash)
+                        .copied()
+                }) {
+                    record_reorged_deposit(log, accepted);
+                    continue;
+                }
+                if read_state(|s| s.is_processed(&log.source())) {
+                    continue;
+                }
+                if !kind.matches(&log.token_contract_address)
+                    || read_state(|s| s.ledger_of(&log.token_contract_address).is_none())
+                {
+                    log!(INFO, "Received deposit of an unsupported token {log:?}");
+                    record_invalid_deposit(
+                        log.source(),
+                        format!("unsupported token {}", log.token_contract_address),
+                    );
+                    continue;
+                }
+                if !transaction_inputs.contains_key(&log.transaction_hash) {
+                    let input = match RpcClient
+                        .get_transaction_input(providers.clone(), log.transaction_hash)
+                        .await
+                    {
+                        Ok(Some(input)) => Some(input),
+                        // A transaction with an oversized input is not a call to `lockTokens`
+                        // with a valid principal.
+                        Err(e) if e.is_response_too_large() => None,
+                        Ok(None) => {
+                            return Err(RpcClientError::InvalidResponse(format!(
+                                "transaction {} of {log:?} not found",
+                                log.transaction_hash
+                            )))
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    transaction_inputs.insert(log.transaction_hash, input);
+                }
+                let transaction_input = &transaction_inputs[&log.transaction_hash];
+                let source = log.source();
+                let event = match transaction_input {
+                    Some(input) => log.into_event(input),
+                    None => Err(EventSourceError::InvalidEvent(format!(
+                        "input of transaction {} is too large",
+                        log.transaction_hash
+                    ))),
+                };
+                match event {
+                    Ok(event) => {
+                        log!(INFO, "Received deposit event {event:?}");
+                        mutate_state(|s| process_event(s, EventType::AcceptedDeposit(event)));
+                    }
+                    Err(error) => {
+                        log!(INFO, "Received invalid deposit event {source}: {error:?}");
+                        record_invalid_deposit(source, format!("{:?}", error));
+                    }
+                }
+            }
+            Err(ReceivedEventError::InvalidEventSource { source, error }) => {
+                if read_state(|s| s.is_processed(&source)) {
+                    continue;
+                }
+                log!(INFO, "Received invalid deposit event {source}: {error:?}");
+                record_invalid_deposit(source, format!("{:?}", error));
+            }
+            Err(ReceivedEventError::RemovedLogEntry { source, block_hash }) => {
+                mutate_state(|s| {
+                    if s.accepted_deposit(&source).is_some()
+                        && s.reorged_deposits.get(&source) != Some(&block_hash)
+                    {
+                        log!(
+                            INFO,
+                            "[ALERT] accepted deposit {source} was removed from the chain by a reorganization"
+                        );
+                        process_event(
+                            s,
+                            EventType::DetectedReorg {
+                                event_source: source,
+                                block_hash,
+                            },
+                        );
+                    }
+                });
+            }
+            Err(ReceivedEventError::InvalidLogEntry(reason)) => {
+                log!(INFO, "Skipping invalid log entry: {reason}");
+            }
+            Err(ReceivedEventError::PendingLogEntry) => {
+                log!(
+                    DEBUG,
+                    "Skipping pending log entry between blocks {from_block} and {to_block}"
+                );
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use candid::{CandidType, Deserialize, Nat, Principal};
+use icrc_ledger_types::icrc2::transfer_from::TransferFromError;
+use minicbor::{Decode, Encode};
+
+use crate::erc20::Erc20TokenArg;
+use crate::evm_rpc_canister::BlockTag;
+use crate::tx::GasFeeEstimate;
+
+/// Block height up to which the minter scrapes deposit logs.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode)]
+#[cbor(index_only)]
+pub enum CandidBlockTag {
+    /// The latest mined block.
+    #[n(0)]
+    Latest,
+    /// The latest safe head block.
+    #[n(1)]
+    Safe,
+    /// The latest finalized block.
+    #[default]
+    #[n(2)]
+    Finalized,
+}
+
+impl From<CandidBlockTag> for BlockTag {
+    fn from(block_tag: CandidBlockTag) -> Self {
+        match block_tag {
+            CandidBlockTag::Latest => BlockTag::Latest,
+            CandidBlockTag::Safe => BlockTag::Safe,
+            CandidBlockTag::Finalized => BlockTag::Finalized,
+        }
+    }
+}
+
+/// The gas fee estimate for the next withdrawal transactions.
+#[derive(CandidType, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct CandidGasFeeEstimate {
+    pub base_fee_per_gas: Nat,
+    pub max_priority_fee_per_gas: Nat,
+    pub max_fee_per_gas: Nat,
+    /// The canister time at which the estimate was computed.
+    pub timestamp: u64,
+}
+
+impl From<(u64, GasFeeEstimate)> for CandidGasFeeEstimate {
+    fn from((timestamp, estimate): (u64, GasFeeEstimate)) -> Self {
+        Self {
+            base_fee_per_gas: estimate.base_fee_per_gas.into(),
+            max_priority_fee_per_gas: estimate.max_priority_fee_per_gas.into(),
+            max_fee_per_gas: estimate.estimate_max_fee_per_gas().into(),
+            timestamp,
+        }
+    }
+}
+
+/// The configuration of the minter and the progress of its tasks.
+#[derive(CandidType, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct MinterInfo {
+    /// The minter's address on Polygon, known once the threshold ECDSA public key is cached.
+    pub minter_address: Option<String>,
+    pub helper_contract_address: Option<String>,
+    pub icmatic_ledger_id: Principal,
+    /// The symbol of the token twinning the native currency of the network.
+    pub icmatic_token_symbol: String,
+    pub minimum_withdrawal_amount: Nat,
+    pub supported_erc20_tokens: Vec<Erc20TokenArg>,
+    /// The last block whose deposits of MATIC were scraped.
+    pub last_scraped_block_number: Nat,
+    /// The last block whose deposits of ERC-20 tokens were scraped.
+    pub last_erc20_scraped_block_number: Nat,
+    /// The last block up to which logs can be scraped, see `ethereum_block_height`.
+    pub last_observed_block_number: Option<Nat>,
+    pub gas_fee_estimate: Option<CandidGasFeeEstimate>,
+    /// The MATIC held by the minter, as computed from its events.
+    pub matic_balance: Nat,
+    /// Number of accepted deposits waiting to be minted.
+    pub pending_deposits: u64,
+    pub minted_deposits: u64,
+    /// Number of deposits that were rejected or quarantined.
+    pub invalid_deposits: u64,
+}
+
+/// A block whose logs could not be scraped.
+#[derive(CandidType, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct SkippedBlock {
+    pub block_number: Nat,
+    /// Number of failed retries.
+    pub attempts: u32,
+    /// The canister time after which the block is scraped again.
+    pub next_retry_at: u64,
+}
+
+/// A deposit of a transaction, identified by its log index.
+#[derive(CandidType, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct Deposit {
+    pub log_index: Nat,
+    pub status: DepositStatus,
+}
+
+#[derive(CandidType, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub enum DepositStatus {
+    /// The deposit was accepted and its tokens are being minted.
+    Pending,
+    Minted {
+        /// The index of the mint transaction on the ledger of the token.
+        mint_block_index: Nat,
+        token_symbol: String,
+    },
+    /// The deposit will never be minted, for the given reason.
+    Invalid(String),
+    /// It is unknown whether the deposit was minted; it needs manual intervention.
+    Quarantined,
+}
+
+#[derive(CandidType, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub enum DepositStatusError {
+    InvalidTransactionHash(String),
+    InvalidLogIndex(String),
+    /// No deposit of the transaction is known: either its block is above the last scraped
+    /// block, or the transaction did not lock tokens in the helper contract.
+    NotScraped {
+        last_scraped_block_number: Nat,
+    },
+}
+
+#[derive(CandidType, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct RetrieveMaticRequest {
+    /// The index of the icMATIC burn transaction on the ledger.
+    pub block_index: Nat,
+}
+
+#[derive(CandidType, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct RetrieveErc20Request {
+    /// The index of the icMATIC burn transaction paying for the transaction fee,
+    /// which identifies the withdrawal.
+    pub icmatic_block_index: Nat,
+    /// The index of the icERC20 burn transaction on the token's ledger.
+    pub erc20_block_index: Nat,
+}
+
+/// A withdrawal request and the stage it reached.
+#[derive(CandidType, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct WithdrawalDetail {
+    /// The index of the icMATIC burn transaction, which identifies the withdrawal.
+    pub withdrawal_id: Nat,
+    pub from: Principal,
+    pub recipient_address: String,
+    /// The withdrawn ERC-20 token, none for MATIC.
+    pub erc20_contract_address: Option<String>,
+    /// The burned amount of the withdrawn token.
+    pub withdrawal_amount: Nat,
+    /// The fee charged in icMATIC, known once the transaction is created for MATIC
+    /// withdrawals, where it is deducted from the withdrawal amount.
+    pub transaction_fee: Option<Nat>,
+    /// The fee actually paid by the minter, known once the transaction is finalized.
+    pub effective_transaction_fee: Option<Nat>,
+    /// The canister time at which the minter accepted the request.
+    pub created_at: u64,
+    pub status: WithdrawalStatus,
+}
+
+#[derive(CandidType, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub enum WithdrawalStatus {
+    /// Waiting for a transaction to be created.
+    Pending,
+    /// The transaction was created and waits to be signed.
+    TxCreated,
+    /// The transaction was signed but not yet accepted by the JSON-RPC providers.
+    TxSigned {
+        transaction_hash: String,
+    },
+    TxSent {
+        transaction_hash: String,
+    },
+    TxFinalized {
+        transaction_hash: String,
+        status: TxFinalizedStatus,
+    },
+    /// No transaction could be sent for the withdrawal, whose tokens are being reimbursed.
+    Abandoned {
+        reason: String,
+    },
+    Reimbursed {
+        /// The failed transaction, if any was mined.
+        transaction_hash: Option<String>,
+        reimbursements: Vec<Reimbursement>,
+    },
+}
+
+#[derive(CandidType, Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TxFinalizedStatus {
+    Success,
+    /// The transaction failed, the withdrawn tokens are being reimbursed.
+    Failure,
+}
+
+/// Tokens minted back to the owner of a withdrawal that did not go through.
+#[derive(CandidType, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct Reimbursement {
+    pub ledger_id: Principal,
+    pub reimbursed_amount: Nat,
+    pub reimbursed_in_block: Nat,
+}
+
+#[derive(CandidType, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub enum WithdrawalError {
+    AmountTooLow { min_withdrawal_amount: Nat },
+    TokenNotSupported(String),
+    InvalidDestination(String),
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TemporarilyUnavailable(String),
+}
+
+impl From<TransferFromError> for WithdrawalError {
+    fn from(transfer_from_error: TransferFromError) -> Self {
+        match transfer_from_error {
+            TransferFromError::InsufficientFunds { balance } => Self::InsufficientFunds { balance },
+            TransferFromError::InsufficientAllowance { allowance } => {
+                Self::InsufficientAllowance { allowance }
+            }
+            TransferFromError::TemporarilyUnavailable => Self::TemporarilyUnavailable(
+                "the icMATIC ledger is temporarily unavailable".to_string(),
+            ),
+            error => Self::TemporarilyUnavailable(format!(
+                "failed to burn icMATIC on the ledger: {error:?}"
+            )),
+        }
+    }
+}
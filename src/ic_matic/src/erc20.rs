@@ -0,0 +1,80 @@
+use candid::{CandidType, Deserialize, Principal};
+use minicbor::{Decode, Encode};
+use std::str::FromStr;
+
+use crate::log_types::address::Address;
+use crate::state::InvalidStateError;
+
+/// An ERC-20 token to support, as given in the init and upgrade arguments.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct Erc20TokenArg {
+    /// The address of the token contract on Polygon.
+    #[n(0)]
+    pub erc20_contract_address: String,
+    /// The ICRC ledger of the twin token on the IC.
+    #[cbor(n(1), with = "crate::cbor::principal")]
+    pub ledger_id: Principal,
+    #[n(2)]
+    pub symbol: String,
+    /// Number of decimals of the token, shared by the ERC-20 contract and the ledger.
+    #[n(3)]
+    pub decimals: u8,
+}
+
+/// An ERC-20 token that can be locked in the helper contract
+/// to mint its twin on the corresponding ICRC ledger.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Erc20Token {
+    pub erc20_contract_address: Address,
+    pub ledger_id: Principal,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+impl From<&Erc20Token> for Erc20TokenArg {
+    fn from(token: &Erc20Token) -> Self {
+        Self {
+            erc20_contract_address: token.erc20_contract_address.to_string(),
+            ledger_id: token.ledger_id,
+            symbol: token.symbol.clone(),
+            decimals: token.decimals,
+        }
+    }
+}
+
+impl TryFrom<Erc20TokenArg> for Erc20Token {
+    type Error = InvalidStateError;
+
+    fn try_from(
+        Erc20TokenArg {
+            erc20_contract_address,
+            ledger_id,
+            symbol,
+            decimals,
+        }: Erc20TokenArg,
+    ) -> Result<Self, Self::Error> {
+        let erc20_contract_address = Address::from_str(&erc20_contract_address)
+            .map_err(|e| InvalidStateError::InvalidErc20Token(format!("ERROR: {e}")))?;
+        if erc20_contract_address == Address::ZERO {
+            return Err(InvalidStateError::InvalidErc20Token(
+                "erc20_contract_address cannot be the zero address".to_string(),
+            ));
+        }
+        if ledger_id == Principal::anonymous() {
+            return Err(InvalidStateError::InvalidErc20Token(
+                "ledger_id cannot be the anonymous principal".to_string(),
+            ));
+        }
+        if symbol.trim().is_empty() {
+            return Err(InvalidStateError::InvalidErc20Token(
+                "symbol cannot be blank".to_string(),
+            ));
+        }
+        Ok(Self {
+            erc20_contract_address,
+            ledger_id,
+            symbol,
+            decimals,
+        })
+    }
+}
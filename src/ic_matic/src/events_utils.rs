@@ -1,24 +1,34 @@
-use candid::{CandidType, Principal};
+use candid::Principal;
 use minicbor::{Decode, Encode};
 use serde::Serialize;
-use std::{
-    convert::{TryFrom, TryInto},
-    fmt,
-    str::FromStr,
-};
+use std::{convert::TryFrom, fmt, str::FromStr};
 
 use crate::{
-    checked_amount::CheckedAmountOf,
     evm_rpc_canister::LogEntry,
     log_types::{address::Address, data::FixedSizeData, hash::Hash},
     numeric::{BlockNumber, LogIndex, Wei},
 };
 
+/// Topic of the deposit event emitted by `TokenLock.sol`:
+/// `keccak256("TokensLocked(address,address,uint256,bytes)")`.
+pub const TOKENS_LOCKED_EVENT_TOPIC: &str =
+    "0xd04bc46dc93f065e7320e2cdc9c8ea8e1acaf085995e9f777cf770a2ee71e655";
+
+/// Selector of the `lockTokens(address,uint256,bytes)` function of `TokenLock.sol`.
+const LOCK_TOKENS_SELECTOR: [u8; 4] = [0x90, 0x62, 0x4a, 0x42];
+
+/// The `token` topic of `TokensLocked` events for deposits of the native currency (MATIC),
+/// i.e. `address(0)` left-padded to 32 bytes.
+pub const NATIVE_TOKEN_TOPIC: &str =
+    "0x0000000000000000000000000000000000000000000000000000000000000000";
+
 /// A unique identifier of the event source: the source transaction hash and the log
 /// entry index.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Encode, Decode)]
 pub struct EventSource {
+    #[n(0)]
     pub transaction_hash: Hash,
+    #[n(1)]
     pub log_index: LogIndex,
 }
 
@@ -28,14 +38,26 @@ impl fmt::Display for EventSource {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Encode, Decode)]
 pub struct ReceivedPolygonEvent {
+    #[n(0)]
     pub transaction_hash: Hash,
+    #[n(1)]
     pub block_number: BlockNumber,
+    #[n(2)]
     pub log_index: LogIndex,
+    #[n(3)]
     pub from_address: Address,
+    #[n(4)]
     pub value: Wei,
+    #[cbor(n(5), with = "crate::cbor::principal")]
     pub principal: Principal,
+    /// `address(0)` for deposits of the native currency.
+    #[n(6)]
+    pub token_contract_address: Address,
+    /// Hash of the block that included the deposit, to detect chain reorganizations.
+    #[n(7)]
+    pub block_hash: Hash,
 }
 
 impl ReceivedPolygonEvent {
@@ -47,9 +69,79 @@ impl ReceivedPolygonEvent {
     }
 }
 
+/// A `TokensLocked` log entry. Its indexed `principalId` parameter is a dynamic `bytes` value,
+/// so the log only holds its keccak256 hash: the principal itself must be recovered
+/// from the transaction that emitted the log, see [`TokensLockedLog::into_event`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokensLockedLog {
+    pub transaction_hash: Hash,
+    pub block_number: BlockNumber,
+    pub block_hash: Hash,
+    pub log_index: LogIndex,
+    pub from_address: Address,
+    pub value: Wei,
+    pub token_contract_address: Address,
+    pub principal_id_hash: Hash,
+}
+
+impl TokensLockedLog {
+    pub fn source(&self) -> EventSource {
+        EventSource {
+            transaction_hash: self.transaction_hash,
+            log_index: self.log_index,
+        }
+    }
+
+    /// Returns the deposit, with the principal decoded from the input of the transaction
+    /// that emitted the log, a call to `lockTokens`, and checked against the hash in the log.
+    /// Deposits made by a contract calling `lockTokens` cannot be recovered.
+    pub fn into_event(
+        self,
+        transaction_input: &[u8],
+    ) -> Result<ReceivedPolygonEvent, EventSourceError> {
+        let principal_id = decode_lock_tokens_principal_id(transaction_input).map_err(|err| {
+            EventSourceError::InvalidEvent(format!(
+                "Invalid lockTokens call in transaction {}: {}",
+                self.transaction_hash, err
+            ))
+        })?;
+        let principal_id_hash = Hash(ic_crypto_sha3::Keccak256::hash(principal_id));
+        if principal_id_hash != self.principal_id_hash {
+            return Err(EventSourceError::InvalidEvent(format!(
+                "Principal ID of transaction {} hashes to {}, expected {}",
+                self.transaction_hash, principal_id_hash, self.principal_id_hash
+            )));
+        }
+        let principal = parse_principal_from_slice(principal_id).map_err(|_err| {
+            EventSourceError::InvalidPrincipal {
+                invalid_principal: principal_id.to_vec(),
+            }
+        })?;
+        Ok(ReceivedPolygonEvent {
+            transaction_hash: self.transaction_hash,
+            block_number: self.block_number,
+            log_index: self.log_index,
+            from_address: self.from_address,
+            value: self.value,
+            principal,
+            token_contract_address: self.token_contract_address,
+            block_hash: self.block_hash,
+        })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ReceivedEventError {
     PendingLogEntry,
+    /// The log entry was removed from the chain by a reorganization. Its transaction may be
+    /// included again in another block, so the event source must not be marked as invalid.
+    RemovedLogEntry {
+        source: EventSource,
+        /// The block the log entry was removed from.
+        block_hash: Hash,
+    },
+    /// The log entry cannot be attributed to a transaction.
+    InvalidLogEntry(String),
     InvalidEventSource {
         source: EventSource,
         error: EventSourceError,
@@ -59,14 +151,27 @@ pub enum ReceivedEventError {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum EventSourceError {
     // failed to decode principal from bytes {invalid_principal}
-    InvalidPrincipal { invalid_principal: FixedSizeData },
+    InvalidPrincipal { invalid_principal: Vec<u8> },
     // invalid ReceivedEthEvent
     InvalidEvent(String),
 }
 
-impl TryFrom<LogEntry> for ReceivedPolygonEvent {
+impl TryFrom<LogEntry> for TokensLockedLog {
     type Error = ReceivedEventError;
 
+    /// Parses a `TokensLocked` event emitted by `TokenLock.sol`:
+    ///
+    /// ```solidity
+    /// event TokensLocked(
+    ///     address user,
+    ///     address indexed token,
+    ///     uint256 indexed amount,
+    ///     bytes indexed principalId
+    /// );
+    /// ```
+    ///
+    /// The indexed parameters are in `topics[1..4]`, the only non-indexed parameter `user`
+    /// is the 32-byte `data` field.
     fn try_from(entry: LogEntry) -> Result<Self, Self::Error> {
         let block_number = entry
             .blockNumber
@@ -74,94 +179,116 @@ impl TryFrom<LogEntry> for ReceivedPolygonEvent {
         let transaction_hash = entry
             .transactionHash
             .ok_or(ReceivedEventError::PendingLogEntry)?;
-        let _transaction_indexSerialize = entry
+        let _transaction_index = entry
             .transactionIndex
             .ok_or(ReceivedEventError::PendingLogEntry)?;
         let log_index = entry.logIndex.ok_or(ReceivedEventError::PendingLogEntry)?;
+        let block_hash = entry.blockHash.ok_or(ReceivedEventError::PendingLogEntry)?;
+        let transaction_hash = Hash::from_str(&transaction_hash).map_err(|e| {
+            ReceivedEventError::InvalidLogEntry(format!(
+                "Invalid transaction hash {}: {}",
+                transaction_hash, e
+            ))
+        })?;
+        let block_hash = Hash::from_str(&block_hash).map_err(|e| {
+            ReceivedEventError::InvalidLogEntry(format!("Invalid block hash {}: {}", block_hash, e))
+        })?;
         let event_source = EventSource {
-            transaction_hash: Hash::from_str(&transaction_hash).unwrap(),
-            log_index: CheckedAmountOf::new(log_index),
+            transaction_hash,
+            log_index: LogIndex::new(log_index),
         };
 
         if entry.removed {
-            return Err(ReceivedEventError::InvalidEventSource {
+            return Err(ReceivedEventError::RemovedLogEntry {
                 source: event_source,
-                error: EventSourceError::InvalidEvent(
-                    "this event has been removed from the chain".to_string(),
-                ),
+                block_hash,
             });
         }
 
-        let parse_address = |address: &FixedSizeData| -> Result<Address, ReceivedEventError> {
-            Address::try_from(&address.0).map_err(|err| ReceivedEventError::InvalidEventSource {
-                source: event_source,
-                error: EventSourceError::InvalidEvent(format!(
-                    "Invalid address in log entry: {}",
-                    err
-                )),
-            })
+        let invalid_event = |reason: String| ReceivedEventError::InvalidEventSource {
+            source: event_source,
+            error: EventSourceError::InvalidEvent(reason),
         };
 
-        let parse_principal = |principal: &FixedSizeData| -> Result<Principal, ReceivedEventError> {
-            parse_principal_from_slice(&principal.0).map_err(|_err| {
-                ReceivedEventError::InvalidEventSource {
-                    source: event_source,
-                    error: EventSourceError::InvalidPrincipal {
-                        invalid_principal: principal.clone(),
-                    },
-                }
-            })
+        if entry.topics.len() != 4 {
+            return Err(invalid_event(format!(
+                "Expected 4 topics for TokensLocked event, got {}",
+                entry.topics.len()
+            )));
+        }
+        if entry.topics[0] != TOKENS_LOCKED_EVENT_TOPIC {
+            return Err(invalid_event(format!(
+                "Expected TokensLocked event, got {}",
+                entry.topics[0]
+            )));
+        }
+
+        let parse_fixed_size_data = |hex: &str| -> Result<FixedSizeData, ReceivedEventError> {
+            FixedSizeData::from_str(hex)
+                .map_err(|err| invalid_event(format!("Invalid 32-byte value {}: {}", hex, err)))
         };
 
-        // We have only one non-indexed data field for Polygon and that is the amount.
-        let value_bytes = FixedSizeData::from_str(&entry.data).map_err(|data| {
-            ReceivedEventError::InvalidEventSource {
-                source: event_source,
-                error: EventSourceError::InvalidEvent(format!(
-                    "Invalid data length; expected 32-byte value, got {}",
-                    hex::encode(data)
-                )),
-            }
+        let parse_address = |address: &FixedSizeData| -> Result<Address, ReceivedEventError> {
+            Address::try_from(&address.0)
+                .map_err(|err| invalid_event(format!("Invalid address in log entry: {}", err)))
+        };
+
+        let token_contract_address = parse_address(&parse_fixed_size_data(&entry.topics[1])?)?;
+        let value = Wei::from_be_bytes(parse_fixed_size_data(&entry.topics[2])?.0);
+        let principal_id_hash = Hash(parse_fixed_size_data(&entry.topics[3])?.0);
+        // `user` is the only non-indexed parameter, ABI-encoded as a single 32-byte word.
+        let user = FixedSizeData::from_str(&entry.data).map_err(|err| {
+            invalid_event(format!(
+                "Invalid data {}; expected a single 32-byte word: {}",
+                entry.data, err
+            ))
         })?;
+        let from_address = parse_address(&user)?;
 
-        // We have 3 indexed topics for ETH events: (hash, from_address, principal) and they need to be extracted,
-        // TODO: Converts topics from String to FixedSizedData
-
-        match entry.topics[0].as_str() {
-            "0x4d84986cd718ed41155c024ee6c78a9396f89afed335ee4cb0713996744b49ee" => {
-                if entry.topics.len() != 3 {
-                    return Err(ReceivedEventError::InvalidEventSource {
-                        source: event_source,
-                        error: EventSourceError::InvalidEvent(format!(
-                            "Expected 3 topics for ReceivedEth event, got {}",
-                            entry.topics.len()
-                        )),
-                    });
-                };
-                let from_address =
-                    parse_address(&FixedSizeData::from_str(&entry.topics[1]).unwrap())?;
-                let principal =
-                    parse_principal(&FixedSizeData::from_str(&entry.topics[1]).unwrap())?;
-                Ok(ReceivedPolygonEvent {
-                    transaction_hash: Hash::from_str(&transaction_hash).unwrap(),
-                    block_number: BlockNumber::new(block_number),
-                    log_index: LogIndex::new(log_index),
-                    from_address,
-                    value: Wei::from_be_bytes(value_bytes.0),
-                    principal,
-                }
-                .into())
-            }
+        Ok(TokensLockedLog {
+            transaction_hash,
+            block_number: BlockNumber::new(block_number),
+            block_hash,
+            log_index: LogIndex::new(log_index),
+            from_address,
+            value,
+            token_contract_address,
+            principal_id_hash,
+        })
+    }
+}
 
-            _ => Err(ReceivedEventError::InvalidEventSource {
-                source: event_source,
-                error: EventSourceError::InvalidEvent(format!(
-                    "Expected either ReceivedPolygon, got {}",
-                    entry.topics[0]
-                )),
-            }),
+/// Decodes the `principalId` argument of the ABI-encoded input of a call to
+/// `lockTokens(address token, uint256 amount, bytes principalId)`.
+///
+/// This method MUST never panic (decode bytes from untrusted sources).
+fn decode_lock_tokens_principal_id(input: &[u8]) -> Result<&[u8], String> {
+    let arguments = input
+        .strip_prefix(&LOCK_TOKENS_SELECTOR[..])
+        .ok_or_else(|| "not a call to lockTokens".to_string())?;
+    let read_usize = |position: usize| -> Result<usize, String> {
+        let word = position
+            .checked_add(32)
+            .and_then(|end| arguments.get(position..end))
+            .ok_or_else(|| format!("input too short to read a word at {position}"))?;
+        let (high, low) = word.split_at(24);
+        if high.iter().any(|byte| *byte != 0) {
+            return Err(format!("value at {position} is too large"));
         }
-    }
+        usize::try_from(u64::from_be_bytes(
+            low.try_into().expect("BUG: words are 32 bytes long"),
+        ))
+        .map_err(|_| format!("value at {position} is too large"))
+    };
+    // `principalId` is the third argument: its head word is the offset of its length,
+    // followed by its bytes.
+    let offset = read_usize(64)?;
+    let length = read_usize(offset)?;
+    offset
+        .checked_add(32)
+        .and_then(|start| Some(start..start.checked_add(length)?))
+        .and_then(|range| arguments.get(range))
+        .ok_or_else(|| format!("principalId of {length} bytes is out of bounds"))
 }
 
 /// Decode a candid::Principal from a slice of at most 32 bytes
@@ -172,7 +299,7 @@ impl TryFrom<LogEntry> for ReceivedPolygonEvent {
 ///
 /// Any other encoding will return an error.
 /// Some specific valid [`Principal`]s are also not allowed
-/// since the decoded principal will be used to receive ckETH:
+/// since the decoded principal will be used to receive icMATIC and icERC20 tokens:
 /// * the management canister principal
 /// * the anonymous principal
 ///
@@ -210,3 +337,238 @@ fn parse_principal_from_slice(slice: &[u8]) -> Result<Principal, String> {
     }
     Principal::try_from_slice(principal_bytes).map_err(|err| err.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // No real fixture exists for the `TokensLocked` event: the only deposit broadcast in the
+    // repository (`broadcast/interactWithIcMATIC.sol/11155111/run-latest.json`, on Sepolia)
+    // emitted the legacy `ReceivedEth` event (topic 0x4d84986c...), and the Amoy broadcast
+    // only deploys the helper contract. The fixtures keep the values of that deposit, 1 gwei
+    // by 0xbaf59b045c6b53bcc849e2a487c14f234435cc51 into the helper contract
+    // 0x0e2e8f489927b62725ae65ecb2c3ed410701a337, and re-encode them in the new format.
+    // They should be replaced by a `lockTokens` transaction on Amoy and its log once one
+    // is broadcast.
+    const TX_HASH: &str = "0xa8c71b00a03de0822642a84013b53554ac881f0e7c10c922ff781cf18efc7d18";
+    const BLOCK_HASH: &str = "0x29b3d975afa40825748aa4198205d7b62074da20c9e483f0efbdf120ceef3476";
+    const CONTRACT_ADDRESS: &str = "0x0e2e8f489927b62725ae65ecb2c3ed410701a337";
+    const USER: &str = "0x000000000000000000000000baf59b045c6b53bcc849e2a487c14f234435cc51";
+    const AMOUNT: &str = "0x000000000000000000000000000000000000000000000000000000003b9aca00";
+    const PRINCIPAL_ID: &str = "0x1d4cc29325adc1e82de0dc2e87dce6e8feb31c0b4ae578881172cf35f9020000";
+    /// `keccak256(PRINCIPAL_ID)`, the indexed `principalId` topic.
+    const PRINCIPAL_ID_HASH: &str =
+        "0x9d12e88917143848ae890d470a3e2ac8cd34ac6102138ee8c78b8cf0865ba436";
+
+    /// The deposit of the broadcast re-encoded as the `TokensLocked` event of `TokenLock.sol`.
+    fn tokens_locked_entry() -> LogEntry {
+        LogEntry {
+            transactionHash: Some(TX_HASH.to_string()),
+            blockNumber: Some(0x5bf820),
+            data: USER.to_string(),
+            blockHash: Some(BLOCK_HASH.to_string()),
+            transactionIndex: Some(0x58),
+            topics: vec![
+                TOKENS_LOCKED_EVENT_TOPIC.to_string(),
+                NATIVE_TOKEN_TOPIC.to_string(),
+                AMOUNT.to_string(),
+                PRINCIPAL_ID_HASH.to_string(),
+            ],
+            address: CONTRACT_ADDRESS.to_string(),
+            logIndex: Some(0xa2),
+            removed: false,
+        }
+    }
+
+    /// Input of the call to `lockTokens(address(0), 1 gwei, principalId)` emitting the deposit.
+    fn lock_tokens_input(principal_id: &[u8]) -> Vec<u8> {
+        let word = |value: usize| {
+            let mut word = [0_u8; 32];
+            word[24..].copy_from_slice(&(value as u64).to_be_bytes());
+            word
+        };
+        let mut input = LOCK_TOKENS_SELECTOR.to_vec();
+        input.extend_from_slice(&[0_u8; 32]);
+        input.extend_from_slice(&FixedSizeData::from_str(AMOUNT).unwrap().0);
+        input.extend_from_slice(&word(96));
+        input.extend_from_slice(&word(principal_id.len()));
+        input.extend_from_slice(principal_id);
+        input.resize(input.len() + (32 - principal_id.len() % 32) % 32, 0);
+        input
+    }
+
+    fn principal_id() -> Vec<u8> {
+        FixedSizeData::from_str(PRINCIPAL_ID).unwrap().0.to_vec()
+    }
+
+    fn expected_source() -> EventSource {
+        EventSource {
+            transaction_hash: Hash::from_str(TX_HASH).unwrap(),
+            log_index: LogIndex::new(162),
+        }
+    }
+
+    #[test]
+    fn should_parse_tokens_locked_event() {
+        let log = TokensLockedLog::try_from(tokens_locked_entry()).unwrap();
+        assert_eq!(log.source(), expected_source());
+        assert_eq!(
+            log.principal_id_hash,
+            Hash::from_str(PRINCIPAL_ID_HASH).unwrap()
+        );
+
+        let event = log.into_event(&lock_tokens_input(&principal_id())).unwrap();
+
+        assert_eq!(
+            event,
+            ReceivedPolygonEvent {
+                transaction_hash: Hash::from_str(TX_HASH).unwrap(),
+                block_number: BlockNumber::new(6_027_296),
+                log_index: LogIndex::new(162),
+                from_address: Address::from_str("0xbaf59b045c6b53bcc849e2a487c14f234435cc51")
+                    .unwrap(),
+                value: Wei::new(1_000_000_000),
+                principal: Principal::from_text(
+                    "matbl-u2myk-jsllo-b5aw6-bxboq-7oon2-h6wmo-awsxf-pcebc-4wpgx-4qe"
+                )
+                .unwrap(),
+                token_contract_address: Address::ZERO,
+                block_hash: Hash::from_str(BLOCK_HASH).unwrap(),
+            }
+        );
+        assert_eq!(event.source(), expected_source());
+    }
+
+    #[test]
+    fn should_reject_principal_not_matching_log_hash() {
+        let log = TokensLockedLog::try_from(tokens_locked_entry()).unwrap();
+        let mut other_principal_id = principal_id();
+        other_principal_id[1] ^= 1;
+
+        assert!(matches!(
+            log.into_event(&lock_tokens_input(&other_principal_id)),
+            Err(EventSourceError::InvalidEvent(_))
+        ));
+    }
+
+    #[test]
+    fn should_reject_transaction_not_calling_lock_tokens() {
+        let log = TokensLockedLog::try_from(tokens_locked_entry()).unwrap();
+        // The input of the `deposit(bytes32)` call of the broadcast.
+        let deposit_input = hex::decode(format!("b214faa5{}", &PRINCIPAL_ID[2..])).unwrap();
+
+        assert!(matches!(
+            log.clone().into_event(&deposit_input),
+            Err(EventSourceError::InvalidEvent(_))
+        ));
+        let truncated_input = lock_tokens_input(&principal_id())[..100].to_vec();
+        assert!(matches!(
+            log.into_event(&truncated_input),
+            Err(EventSourceError::InvalidEvent(_))
+        ));
+    }
+
+    #[test]
+    fn should_parse_erc20_token_address() {
+        let token = "0x0000000000000000000000000e2e8f489927b62725ae65ecb2c3ed410701a337";
+        let mut entry = tokens_locked_entry();
+        entry.topics[1] = token.to_string();
+
+        let event = TokensLockedLog::try_from(entry).unwrap();
+
+        assert_eq!(
+            event.token_contract_address,
+            Address::from_str(CONTRACT_ADDRESS).unwrap()
+        );
+    }
+
+    #[test]
+    fn should_reject_legacy_deposit_event() {
+        // The log actually emitted by the broadcast transaction, from the former
+        // helper contract that indexed the principal in `topics[2]`.
+        let mut entry = tokens_locked_entry();
+        entry.topics = vec![
+            "0x4d84986cd718ed41155c024ee6c78a9396f89afed335ee4cb0713996744b49ee".to_string(),
+            USER.to_string(),
+            PRINCIPAL_ID.to_string(),
+        ];
+        entry.data = AMOUNT.to_string();
+
+        assert_eq!(
+            TokensLockedLog::try_from(entry),
+            Err(ReceivedEventError::InvalidEventSource {
+                source: expected_source(),
+                error: EventSourceError::InvalidEvent(
+                    "Expected 4 topics for TokensLocked event, got 3".to_string()
+                ),
+            })
+        );
+    }
+
+    #[test]
+    fn should_reject_unknown_event_topic() {
+        let mut entry = tokens_locked_entry();
+        entry.topics[0] =
+            "0x4d84986cd718ed41155c024ee6c78a9396f89afed335ee4cb0713996744b49ee".to_string();
+
+        assert_matches_invalid_event(TokensLockedLog::try_from(entry));
+    }
+
+    #[test]
+    fn should_report_removed_entry() {
+        let mut entry = tokens_locked_entry();
+        entry.removed = true;
+
+        assert_eq!(
+            TokensLockedLog::try_from(entry),
+            Err(ReceivedEventError::RemovedLogEntry {
+                source: expected_source(),
+                block_hash: Hash::from_str(BLOCK_HASH).unwrap(),
+            })
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_principal() {
+        let anonymous = [1_u8, 4];
+        let mut entry = tokens_locked_entry();
+        entry.topics[3] = Hash(ic_crypto_sha3::Keccak256::hash(&anonymous[..])).to_string();
+        let log = TokensLockedLog::try_from(entry).unwrap();
+
+        assert_eq!(
+            log.into_event(&lock_tokens_input(&anonymous)),
+            Err(EventSourceError::InvalidPrincipal {
+                invalid_principal: anonymous.to_vec(),
+            })
+        );
+    }
+
+    #[test]
+    fn should_reject_data_with_more_than_one_word() {
+        let mut entry = tokens_locked_entry();
+        entry.data = format!("{}{}", USER, &AMOUNT[2..]);
+
+        assert_matches_invalid_event(TokensLockedLog::try_from(entry));
+    }
+
+    #[test]
+    fn should_report_pending_entry() {
+        let mut entry = tokens_locked_entry();
+        entry.blockNumber = None;
+
+        assert_eq!(
+            TokensLockedLog::try_from(entry),
+            Err(ReceivedEventError::PendingLogEntry)
+        );
+    }
+
+    fn assert_matches_invalid_event(result: Result<TokensLockedLog, ReceivedEventError>) {
+        match result {
+            Err(ReceivedEventError::InvalidEventSource {
+                source,
+                error: EventSourceError::InvalidEvent(_),
+            }) => assert_eq!(source, expected_source()),
+            other => panic!("expected an invalid event error, got {:?}", other),
+        }
+    }
+}
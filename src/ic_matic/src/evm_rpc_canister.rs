@@ -17,7 +17,7 @@ pub enum Auth {
     Manage,
 }
 
-#[derive(CandidType, Deserialize)]
+#[derive(CandidType, Debug, Deserialize)]
 pub enum EthSepoliaService {
     Alchemy,
     BlockPi,
@@ -25,7 +25,7 @@ pub enum EthSepoliaService {
     Ankr,
 }
 
-#[derive(CandidType, Deserialize)]
+#[derive(CandidType, Debug, Deserialize)]
 pub enum L2MainnetService {
     Alchemy,
     BlockPi,
@@ -33,19 +33,19 @@ pub enum L2MainnetService {
     Ankr,
 }
 
-#[derive(CandidType, Deserialize)]
+#[derive(CandidType, Clone, Deserialize)]
 pub struct HttpHeader {
     pub value: String,
     pub name: String,
 }
 
-#[derive(CandidType, Deserialize)]
+#[derive(CandidType, Clone, Deserialize)]
 pub struct RpcApi {
     pub url: String,
     pub headers: Option<Vec<HttpHeader>>,
 }
 
-#[derive(CandidType, Deserialize)]
+#[derive(CandidType, Debug, Deserialize)]
 pub enum EthMainnetService {
     Alchemy,
     BlockPi,
@@ -69,7 +69,7 @@ pub struct RpcConfig {
     pub responseSizeEstimate: Option<u64>,
 }
 
-#[derive(CandidType, Deserialize)]
+#[derive(CandidType, Clone, Debug, Deserialize)]
 pub enum BlockTag {
     Earliest,
     Safe,
@@ -79,7 +79,7 @@ pub enum BlockTag {
     Pending,
 }
 
-#[derive(CandidType, Deserialize)]
+#[derive(CandidType, Debug, Deserialize)]
 pub struct FeeHistoryArgs {
     pub blockCount: u128,
     pub newestBlock: BlockTag,
@@ -292,7 +292,7 @@ pub enum MultiGetTransactionReceiptResult {
     Inconsistent(Vec<(RpcService, GetTransactionReceiptResult)>),
 }
 
-#[derive(CandidType, Deserialize)]
+#[derive(CandidType, Debug, Deserialize)]
 pub enum SendRawTransactionStatus {
     Ok(Option<String>),
     NonceTooLow,
@@ -359,7 +359,7 @@ pub enum RequestResult {
     Err(RpcError),
 }
 
-#[derive(CandidType, Deserialize)]
+#[derive(CandidType, Debug, Deserialize)]
 pub enum RequestCostResult {
     Ok(u128),
     Err(RpcError),
@@ -394,46 +394,81 @@ impl EmvRpcService {
         .await
     }
 
-    // pub async fn eth_fee_history(
-    //     &self,
-    //     arg0: RpcServices,
-    //     arg1: Option<RpcConfig>,
-    //     arg2: FeeHistoryArgs,
-    // ) -> Result<(MultiFeeHistoryResult,)> {
-    //     ic_cdk::call(CANISTER_ID, "eth_feeHistory", (arg0, arg1, arg2)).await
-    // }
-    // pub async fn eth_get_block_by_number(
-    //     &self,
-    //     arg0: RpcServices,
-    //     arg1: Option<RpcConfig>,
-    //     arg2: BlockTag,
-    // ) -> Result<(MultiGetBlockByNumberResult,)> {
-    //     ic_cdk::call(CANISTER_ID, "eth_getBlockByNumber", (arg0, arg1, arg2)).await
-    // }
-    // pub async fn eth_get_transaction_count(
-    //     &self,
-    //     arg0: RpcServices,
-    //     arg1: Option<RpcConfig>,
-    //     arg2: GetTransactionCountArgs,
-    // ) -> Result<(MultiGetTransactionCountResult,)> {
-    //     ic_cdk::call(CANISTER_ID, "eth_getTransactionCount", (arg0, arg1, arg2)).await
-    // }
-    // pub async fn eth_get_transaction_receipt(
-    //     &self,
-    //     arg0: RpcServices,
-    //     arg1: Option<RpcConfig>,
-    //     arg2: String,
-    // ) -> Result<(MultiGetTransactionReceiptResult,)> {
-    //     ic_cdk::call(CANISTER_ID, "eth_getTransactionReceipt", (arg0, arg1, arg2)).await
-    // }
-    // pub async fn eth_send_raw_transaction(
-    //     &self,
-    //     arg0: RpcServices,
-    //     arg1: Option<RpcConfig>,
-    //     arg2: String,
-    // ) -> Result<(MultiSendRawTransactionResult,)> {
-    //     ic_cdk::call(CANISTER_ID, "eth_sendRawTransaction", (arg0, arg1, arg2)).await
-    // }
+    pub async fn eth_fee_history(
+        &self,
+        arg0: RpcServices,
+        arg1: Option<RpcConfig>,
+        arg2: FeeHistoryArgs,
+        cycles: u128,
+    ) -> Result<(MultiFeeHistoryResult,)> {
+        ic_cdk::api::call::call_with_payment128(
+            CANISTER_ID,
+            "eth_feeHistory",
+            (arg0, arg1, arg2),
+            cycles,
+        )
+        .await
+    }
+    pub async fn eth_get_block_by_number(
+        &self,
+        arg0: RpcServices,
+        arg1: Option<RpcConfig>,
+        arg2: BlockTag,
+        cycles: u128,
+    ) -> Result<(MultiGetBlockByNumberResult,)> {
+        ic_cdk::api::call::call_with_payment128(
+            CANISTER_ID,
+            "eth_getBlockByNumber",
+            (arg0, arg1, arg2),
+            cycles,
+        )
+        .await
+    }
+    pub async fn eth_get_transaction_count(
+        &self,
+        arg0: RpcServices,
+        arg1: Option<RpcConfig>,
+        arg2: GetTransactionCountArgs,
+        cycles: u128,
+    ) -> Result<(MultiGetTransactionCountResult,)> {
+        ic_cdk::api::call::call_with_payment128(
+            CANISTER_ID,
+            "eth_getTransactionCount",
+            (arg0, arg1, arg2),
+            cycles,
+        )
+        .await
+    }
+    pub async fn eth_get_transaction_receipt(
+        &self,
+        arg0: RpcServices,
+        arg1: Option<RpcConfig>,
+        arg2: String,
+        cycles: u128,
+    ) -> Result<(MultiGetTransactionReceiptResult,)> {
+        ic_cdk::api::call::call_with_payment128(
+            CANISTER_ID,
+            "eth_getTransactionReceipt",
+            (arg0, arg1, arg2),
+            cycles,
+        )
+        .await
+    }
+    pub async fn eth_send_raw_transaction(
+        &self,
+        arg0: RpcServices,
+        arg1: Option<RpcConfig>,
+        arg2: String,
+        cycles: u128,
+    ) -> Result<(MultiSendRawTransactionResult,)> {
+        ic_cdk::api::call::call_with_payment128(
+            CANISTER_ID,
+            "eth_sendRawTransaction",
+            (arg0, arg1, arg2),
+            cycles,
+        )
+        .await
+    }
     // pub async fn get_accumulated_cycle_count(&self, arg0: ProviderId) -> Result<(u128,)> {
     //     ic_cdk::call(CANISTER_ID, "getAccumulatedCycleCount", (arg0,)).await
     // }
@@ -461,22 +496,24 @@ impl EmvRpcService {
     // pub async fn register_provider(&self, arg0: RegisterProviderArgs) -> Result<(u64,)> {
     //     ic_cdk::call(CANISTER_ID, "registerProvider", (arg0,)).await
     // }
-    // pub async fn request(
-    //     &self,
-    //     arg0: RpcService,
-    //     arg1: String,
-    //     arg2: u64,
-    // ) -> Result<(RequestResult,)> {
-    //     ic_cdk::call(CANISTER_ID, "request", (arg0, arg1, arg2)).await
-    // }
-    // pub async fn request_cost(
-    //     &self,
-    //     arg0: RpcService,
-    //     arg1: String,
-    //     arg2: u64,
-    // ) -> Result<(RequestCostResult,)> {
-    //     ic_cdk::call(CANISTER_ID, "requestCost", (arg0, arg1, arg2)).await
-    // }
+    pub async fn request(
+        &self,
+        arg0: RpcService,
+        arg1: String,
+        arg2: u64,
+        cycles: u128,
+    ) -> Result<(RequestResult,)> {
+        ic_cdk::api::call::call_with_payment128(CANISTER_ID, "request", (arg0, arg1, arg2), cycles)
+            .await
+    }
+    pub async fn request_cost(
+        &self,
+        arg0: RpcService,
+        arg1: String,
+        arg2: u64,
+    ) -> Result<(RequestCostResult,)> {
+        ic_cdk::call(CANISTER_ID, "requestCost", (arg0, arg1, arg2)).await
+    }
     // pub async fn set_open_rpc_access(&self, arg0: bool) -> Result<()> {
     //     ic_cdk::call(CANISTER_ID, "setOpenRpcAccess", (arg0,)).await
     // }
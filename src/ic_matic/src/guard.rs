@@ -0,0 +1,73 @@
+use candid::Principal;
+
+use crate::state::{mutate_state, TaskType};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TimerGuardError {
+    AlreadyProcessing,
+}
+
+/// Prevents concurrent executions of the same timer task.
+/// The lock is released when the guard is dropped.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TimerGuard {
+    task: TaskType,
+}
+
+impl TimerGuard {
+    pub fn new(task: TaskType) -> Result<Self, TimerGuardError> {
+        mutate_state(|s| {
+            if !s.active_tasks.insert(task) {
+                return Err(TimerGuardError::AlreadyProcessing);
+            }
+            Ok(Self { task })
+        })
+    }
+}
+
+impl Drop for TimerGuard {
+    fn drop(&mut self) {
+        mutate_state(|s| {
+            s.active_tasks.remove(&self.task);
+        });
+    }
+}
+
+/// Maximum number of principals with a withdrawal in progress.
+pub const MAX_CONCURRENT: usize = 100;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum GuardError {
+    AlreadyProcessing,
+    TooManyConcurrentRequests,
+}
+
+/// Prevents a principal from issuing concurrent withdrawal requests.
+/// The lock is released when the guard is dropped.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RetrieveMaticGuard {
+    principal: Principal,
+}
+
+impl RetrieveMaticGuard {
+    pub fn new(principal: Principal) -> Result<Self, GuardError> {
+        mutate_state(|s| {
+            if s.pending_withdrawal_principals.contains(&principal) {
+                return Err(GuardError::AlreadyProcessing);
+            }
+            if s.pending_withdrawal_principals.len() >= MAX_CONCURRENT {
+                return Err(GuardError::TooManyConcurrentRequests);
+            }
+            s.pending_withdrawal_principals.insert(principal);
+            Ok(Self { principal })
+        })
+    }
+}
+
+impl Drop for RetrieveMaticGuard {
+    fn drop(&mut self) {
+        mutate_state(|s| {
+            s.pending_withdrawal_principals.remove(&self.principal);
+        });
+    }
+}
@@ -1,61 +1,345 @@
+mod cbor;
 mod checked_amount;
+mod deposit;
+mod endpoints;
+mod erc20;
 mod events_utils;
 mod evm_rpc_canister;
+mod guard;
+mod lifecycle;
 mod log_types;
+mod logs;
+mod management;
+mod memo;
 pub mod numeric;
+mod rpc_client;
 mod rpc_providers;
 mod state;
-use candid::candid_method;
-use candid::CandidType;
-use events_utils::ReceivedPolygonEvent;
-use evm_rpc_canister::EthSepoliaService;
-use evm_rpc_canister::MultiGetLogsResult;
-use evm_rpc_canister::{
-    BlockTag, EmvRpcService, GetLogsArgs, GetLogsResult, RpcApi, RpcConfig, RpcError, RpcService,
-    RpcServices,
+mod storage;
+mod tx;
+mod withdraw;
+use candid::{Nat, Principal};
+use deposit::{retry_skipped_blocks, scrape_eth_logs};
+use endpoints::{
+    CandidGasFeeEstimate, Deposit, DepositStatusError, MinterInfo, RetrieveErc20Request,
+    RetrieveMaticRequest, SkippedBlock, WithdrawalDetail, WithdrawalError,
 };
-use ic_cdk::api::call::RejectionCode;
-use ic_cdk::api::management_canister::http_request::{HttpResponse, TransformArgs};
-use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
-use serde::{Deserialize, Serialize};
-use serde_json::to_string_pretty;
-// use minter::polygon_rpc_client::{providers, PolygonRPCWorker};
-
-#[update]
-async fn get_logs(cycles: u128) -> Result<String, String> {
-    let sepolia_services: RpcServices =
-        RpcServices::EthSepolia(Some(vec![EthSepoliaService::Alchemy]));
-    let get_logs_args: GetLogsArgs = GetLogsArgs {
-        fromBlock: Some(BlockTag::Number(272851)),
-        toBlock: Some(BlockTag::Latest),
-        addresses: vec!["0x0e2e8f489927b62725ae65ecb2c3ed410701a337".to_string()],
-        topics: None,
-    };
-    let log_results = EmvRpcService
-        .eth_get_logs(sepolia_services, None, get_logs_args, cycles)
-        .await
-        .unwrap();
-
-    let mut log_entries: Vec<ReceivedPolygonEvent> = Vec::new();
-    match log_results {
-        (MultiGetLogsResult::Consistent(consistent_data),) => match consistent_data {
-            GetLogsResult::Ok(logEntries) => {
-                for logEntry in logEntries {
-                    let new_log_entry: Result<
-                        ReceivedPolygonEvent,
-                        events_utils::ReceivedEventError,
-                    > = ReceivedPolygonEvent::try_from(logEntry);
-                    log_entries.push(new_log_entry.unwrap());
-                }
-                let json = to_string_pretty(&log_entries).unwrap();
-                return Ok(json);
+use erc20::{Erc20Token, Erc20TokenArg};
+use ic_cdk_macros::{init, post_upgrade, query, update};
+use lifecycle::MinterArg;
+use log_types::address::Address;
+use log_types::hash::Hash;
+use numeric::{BlockNumber, LedgerBurnIndex, LogIndex, Wei};
+use rpc_providers::RpcProvider;
+use state::audit::process_event;
+use state::event::EventType;
+use state::{mutate_state, read_state, State, STATE};
+use std::str::FromStr;
+use std::time::Duration;
+use tx::refresh_gas_fee_estimate;
+use withdraw::{process_reimbursement, process_retrieve_matic_requests};
+
+/// Number of blocks produced between two scrapings of the logs.
+pub const SCRAPING_ETH_LOGS_INTERVAL_BLOCKS: u32 = 90;
+pub const MINT_RETRY_DELAY: Duration = Duration::from_secs(3 * 60);
+pub const PROCESS_MATIC_RETRIEVE_TRANSACTIONS_INTERVAL: Duration = Duration::from_secs(60);
+pub const REFRESH_GAS_FEE_ESTIMATE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+pub const PROCESS_REIMBURSEMENT_INTERVAL: Duration = Duration::from_secs(3 * 60);
+/// Number of blocks produced between two retries of the skipped blocks.
+pub const RETRY_SKIPPED_BLOCKS_INTERVAL_BLOCKS: u32 = 300;
+
+/// Maximum number of withdrawals returned by a single `withdrawals_by_principal` call.
+const MAX_WITHDRAWALS_PAGE_SIZE: u64 = 100;
+
+fn setup_timers() {
+    let (scraping_interval, retry_skipped_blocks_interval) = read_state(|s| {
+        (
+            s.evm_network
+                .blocks_duration(SCRAPING_ETH_LOGS_INTERVAL_BLOCKS),
+            s.evm_network
+                .blocks_duration(RETRY_SKIPPED_BLOCKS_INTERVAL_BLOCKS),
+        )
+    });
+    // Start scraping logs immediately after the install, then repeat with the interval.
+    ic_cdk_timers::set_timer(Duration::from_secs(0), || ic_cdk::spawn(scrape_eth_logs()));
+    ic_cdk_timers::set_timer_interval(scraping_interval, || ic_cdk::spawn(scrape_eth_logs()));
+    ic_cdk_timers::set_timer_interval(PROCESS_MATIC_RETRIEVE_TRANSACTIONS_INTERVAL, || {
+        ic_cdk::spawn(process_retrieve_matic_requests())
+    });
+    ic_cdk_timers::set_timer_interval(PROCESS_REIMBURSEMENT_INTERVAL, || {
+        ic_cdk::spawn(process_reimbursement())
+    });
+    ic_cdk_timers::set_timer_interval(retry_skipped_blocks_interval, || {
+        ic_cdk::spawn(retry_skipped_blocks())
+    });
+    ic_cdk_timers::set_timer_interval(REFRESH_GAS_FEE_ESTIMATE_INTERVAL, || {
+        ic_cdk::spawn(async {
+            let _ = refresh_gas_fee_estimate().await;
+        })
+    });
+}
+
+#[init]
+fn init(arg: MinterArg) {
+    match arg {
+        MinterArg::InitArg(init_arg) => match State::try_from(init_arg.clone()) {
+            Ok(state) => {
+                STATE.with(|cell| *cell.borrow_mut() = Some(state));
+                storage::record_event(EventType::Init(init_arg));
             }
-            GetLogsResult::Err(error) => Err(format!("rpc error,{:?}", error).to_string()),
+            Err(e) => ic_cdk::trap(&format!("failed to initialize minter: {:?}", e)),
         },
+        MinterArg::UpgradeArg(_) => {
+            ic_cdk::trap("cannot init canister state without init args");
+        }
+    }
+    setup_timers();
+}
+
+#[post_upgrade]
+fn post_upgrade(minter_arg: Option<MinterArg>) {
+    match minter_arg {
+        Some(MinterArg::InitArg(_)) => {
+            ic_cdk::trap("cannot upgrade canister state with init args");
+        }
+        Some(MinterArg::UpgradeArg(upgrade_arg)) => {
+            lifecycle::upgrade::post_upgrade(Some(upgrade_arg))
+        }
+        None => lifecycle::upgrade::post_upgrade(None),
+    }
+    setup_timers();
+}
+
+/// Returns the minter's address on Polygon, which sends the withdrawal transactions.
+#[update]
+async fn minter_address() -> String {
+    state::minter_address()
+        .await
+        .unwrap_or_else(|e| ic_cdk::trap(&format!("failed to get the minter address: {e}")))
+        .to_string()
+}
+
+/// Returns the last gas fee estimate, if any, used to price withdrawal transactions.
+#[query]
+fn gas_fee_estimate() -> Option<CandidGasFeeEstimate> {
+    read_state(|s| {
+        s.last_transaction_price_estimate
+            .map(CandidGasFeeEstimate::from)
+    })
+}
 
-        (MultiGetLogsResult::Inconsistent(inconsistent_data),) => {
-            Ok("Inconsistent data".to_string())
+/// Summarizes the configuration of the minter and the progress of its tasks.
+#[query]
+fn get_minter_info() -> MinterInfo {
+    read_state(|s| MinterInfo {
+        minter_address: s.minter_address().map(|address| address.to_string()),
+        helper_contract_address: s
+            .eth_helper_contract_address
+            .map(|address| address.to_string()),
+        icmatic_ledger_id: s.icmatic_ledger_id,
+        icmatic_token_symbol: s.icmatic_token_symbol(),
+        minimum_withdrawal_amount: s.icmatic_minimum_withdrawal_amount.into(),
+        supported_erc20_tokens: s.erc20_tokens.values().map(Erc20TokenArg::from).collect(),
+        last_scraped_block_number: s.last_scraped_block_number.into(),
+        last_erc20_scraped_block_number: s.last_erc20_scraped_block_number.into(),
+        last_observed_block_number: s.last_observed_block_number.map(Nat::from),
+        gas_fee_estimate: s
+            .last_transaction_price_estimate
+            .map(CandidGasFeeEstimate::from),
+        matic_balance: Wei::from_be_bytes(s.matic_balance.to_be_bytes()).into(),
+        pending_deposits: s.events_to_mint.len() as u64,
+        minted_deposits: s.minted_events.len() as u64,
+        invalid_deposits: s.invalid_events.len() as u64,
+    })
+}
+
+/// Returns the status of the deposits of a transaction that locked tokens in the helper
+/// contract, or only of its deposit with the given log index.
+#[query]
+fn deposit_status(
+    transaction_hash: String,
+    log_index: Option<Nat>,
+) -> Result<Vec<Deposit>, DepositStatusError> {
+    let transaction_hash =
+        Hash::from_str(&transaction_hash).map_err(DepositStatusError::InvalidTransactionHash)?;
+    let log_index = log_index
+        .map(LogIndex::try_from)
+        .transpose()
+        .map_err(DepositStatusError::InvalidLogIndex)?;
+    read_state(|s| {
+        let deposits: Vec<_> = s
+            .deposit_statuses(transaction_hash)
+            .into_iter()
+            .filter(|(index, _status)| log_index.map_or(true, |log_index| *index == log_index))
+            .map(|(index, status)| Deposit {
+                log_index: index.into(),
+                status,
+            })
+            .collect();
+        if deposits.is_empty() {
+            // Deposits of MATIC and of ERC-20 tokens are scraped with their own cursor.
+            let last_scraped_block_number = s
+                .last_scraped_block_number
+                .min(s.last_erc20_scraped_block_number);
+            return Err(DepositStatusError::NotScraped {
+                last_scraped_block_number: last_scraped_block_number.into(),
+            });
         }
+        Ok(deposits)
+    })
+}
+
+#[update]
+async fn withdraw_matic(
+    amount: Nat,
+    recipient: String,
+) -> Result<RetrieveMaticRequest, WithdrawalError> {
+    withdraw::withdraw_matic(amount, recipient).await
+}
+
+#[update]
+async fn withdraw_erc20(
+    amount: Nat,
+    erc20_contract_address: String,
+    recipient: String,
+) -> Result<RetrieveErc20Request, WithdrawalError> {
+    withdraw::withdraw_erc20(amount, erc20_contract_address, recipient).await
+}
+
+/// Returns the withdrawal identified by the index of its icMATIC burn transaction.
+#[query]
+fn withdrawal_status(burn_index: u64) -> Option<WithdrawalDetail> {
+    read_state(|s| s.withdrawal_detail(&LedgerBurnIndex::new(burn_index)))
+}
+
+/// Returns the withdrawals of the given principal, the most recent first, skipping
+/// `offset` of them and returning at most `limit`.
+#[query]
+fn withdrawals_by_principal(
+    principal: Principal,
+    offset: u64,
+    limit: u64,
+) -> Vec<WithdrawalDetail> {
+    read_state(|s| {
+        let mut withdrawal_ids: Vec<_> = s
+            .polygon_transactions
+            .withdrawal_requests()
+            .filter(|request| request.from() == principal)
+            .map(|request| request.withdrawal_id())
+            .collect();
+        withdrawal_ids.sort_unstable_by(|a, b| b.cmp(a));
+        withdrawal_ids
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit.min(MAX_WITHDRAWALS_PAGE_SIZE) as usize)
+            .filter_map(|withdrawal_id| s.withdrawal_detail(&withdrawal_id))
+            .collect()
+    })
+}
+
+/// Adds a supported ERC-20 token. Only callable by a controller of the minter.
+#[update]
+fn add_erc20_token(token: Erc20TokenArg) -> Result<(), String> {
+    ensure_controller();
+    let erc20_token = Erc20Token::try_from(token.clone()).map_err(|e| format!("{e:?}"))?;
+    mutate_state(|s| {
+        s.validate_erc20_token_addition(&erc20_token)
+            .map_err(|e| format!("{e:?}"))?;
+        process_event(s, EventType::AddedErc20Token(token));
+        Ok(())
+    })
+}
+
+/// Removes a supported ERC-20 token, whose deposits are then ignored and whose
+/// withdrawals are rejected. Only callable by a controller of the minter.
+#[update]
+fn remove_erc20_token(erc20_contract_address: String) -> Result<(), String> {
+    ensure_controller();
+    let erc20_contract_address =
+        Address::from_str(&erc20_contract_address).map_err(|e| format!("{e}"))?;
+    mutate_state(|s| {
+        s.validate_erc20_token_removal(&erc20_contract_address)
+            .map_err(|e| format!("{e:?}"))?;
+        process_event(
+            s,
+            EventType::RemovedErc20Token {
+                erc20_contract_address,
+            },
+        );
+        Ok(())
+    })
+}
+
+/// Replaces the JSON-RPC providers of the Polygon network.
+/// Only callable by a controller of the minter.
+#[update]
+fn update_rpc_providers(providers: Vec<RpcProvider>) -> Result<(), String> {
+    ensure_controller();
+    rpc_providers::validate_rpc_providers(&providers).map_err(|e| format!("{e:?}"))?;
+    read_state(|s| state::validate_consensus_strategy(&s.consensus_strategy, providers.len()))
+        .map_err(|e| format!("{e:?}"))?;
+    mutate_state(|s| process_event(s, EventType::UpdatedRpcProviders { providers }));
+    Ok(())
+}
+
+/// Lists the blocks whose logs could not be scraped.
+/// Only callable by a controller of the minter.
+#[query]
+fn get_skipped_blocks() -> Vec<SkippedBlock> {
+    ensure_controller();
+    read_state(|s| {
+        s.skipped_blocks
+            .iter()
+            .map(|block_number| {
+                let retry = s
+                    .skipped_block_retries
+                    .get(block_number)
+                    .copied()
+                    .unwrap_or_default();
+                SkippedBlock {
+                    block_number: (*block_number).into(),
+                    attempts: retry.attempts,
+                    next_retry_at: retry.next_retry_at,
+                }
+            })
+            .collect()
+    })
+}
+
+/// Retries scraping the logs of a skipped block now.
+/// Only callable by a controller of the minter.
+#[update]
+fn retry_skipped_block(block_number: Nat) -> Result<(), String> {
+    ensure_controller();
+    let block_number = skipped_block(block_number)?;
+    mutate_state(|s| process_event(s, EventType::ForcedSkippedBlockRetry { block_number }));
+    ic_cdk_timers::set_timer(Duration::from_secs(0), || {
+        ic_cdk::spawn(retry_skipped_blocks())
+    });
+    Ok(())
+}
+
+/// Gives up on scraping the logs of a skipped block.
+/// Only callable by a controller of the minter.
+#[update]
+fn abandon_skipped_block(block_number: Nat) -> Result<(), String> {
+    ensure_controller();
+    let block_number = skipped_block(block_number)?;
+    mutate_state(|s| process_event(s, EventType::AbandonedSkippedBlock { block_number }));
+    Ok(())
+}
+
+fn skipped_block(block_number: Nat) -> Result<BlockNumber, String> {
+    let block_number = BlockNumber::try_from(block_number)?;
+    if !read_state(|s| s.skipped_blocks.contains(&block_number)) {
+        return Err(format!("block {block_number} was not skipped"));
+    }
+    Ok(block_number)
+}
+
+fn ensure_controller() {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        ic_cdk::trap("only a controller of the minter can call this method");
     }
 }
 
@@ -0,0 +1,138 @@
+use candid::{CandidType, Deserialize, Nat, Principal};
+use minicbor::{Decode, Encode};
+use std::str::FromStr;
+
+use crate::{
+    endpoints::CandidBlockTag,
+    erc20::{Erc20Token, Erc20TokenArg},
+    log_types::address::Address,
+    numeric::{BlockNumber, TransactionNonce, Wei},
+    rpc_client::ConsensusStrategy,
+    rpc_providers::{EvmNetwork, PolygonNetwork},
+    state::{transactions::PolygonTransactions, InvalidStateError, State},
+};
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct InitArg {
+    /// The Polygon network to target, required unless `evm_network` is given.
+    #[n(0)]
+    pub polygon_network: Option<PolygonNetwork>,
+    #[n(1)]
+    pub ecdsa_key_name: String,
+    #[n(2)]
+    pub helper_contract_address: Option<String>,
+    #[cbor(n(3), with = "crate::cbor::principal")]
+    pub icmatic_ledger_id: Principal,
+    #[cbor(n(4), with = "crate::cbor::nat")]
+    pub minimum_withdrawal_amount: Nat,
+    #[cbor(n(5), with = "crate::cbor::nat")]
+    pub last_scraped_block_number: Nat,
+    #[n(6)]
+    pub ethereum_block_height: CandidBlockTag,
+    #[n(7)]
+    pub erc20_tokens: Option<Vec<Erc20TokenArg>>,
+    /// How differing results of the JSON-RPC providers are reduced, `Equality` by default.
+    #[n(8)]
+    pub consensus_strategy: Option<ConsensusStrategy>,
+    /// The EVM chain to target instead of `polygon_network`.
+    #[n(9)]
+    pub evm_network: Option<EvmNetwork>,
+    /// Number of confirmations of the logs scraped when `ethereum_block_height` is `Latest`,
+    /// the finality depth of the network by default.
+    #[n(10)]
+    pub latest_block_confirmations: Option<u64>,
+}
+
+impl TryFrom<InitArg> for State {
+    type Error = InvalidStateError;
+
+    fn try_from(
+        InitArg {
+            polygon_network,
+            ecdsa_key_name,
+            helper_contract_address,
+            icmatic_ledger_id,
+            minimum_withdrawal_amount,
+            last_scraped_block_number,
+            ethereum_block_height,
+            erc20_tokens,
+            consensus_strategy,
+            evm_network,
+            latest_block_confirmations,
+        }: InitArg,
+    ) -> Result<Self, Self::Error> {
+        let eth_helper_contract_address = helper_contract_address
+            .map(|address| Address::from_str(&address))
+            .transpose()
+            .map_err(|e| {
+                InvalidStateError::InvalidEthereumContractAddress(format!("ERROR: {e}"))
+            })?;
+        let icmatic_minimum_withdrawal_amount =
+            Wei::try_from(minimum_withdrawal_amount).map_err(|e| {
+                InvalidStateError::InvalidMinimumWithdrawalAmount(format!("ERROR: {e}"))
+            })?;
+        let last_scraped_block_number = BlockNumber::try_from(last_scraped_block_number)
+            .map_err(|e| InvalidStateError::InvalidLastScrapedBlockNumber(format!("ERROR: {e}")))?;
+        let first_scraped_block_number =
+            last_scraped_block_number
+                .checked_increment()
+                .ok_or_else(|| {
+                    InvalidStateError::InvalidLastScrapedBlockNumber(
+                        "ERROR: last_scraped_block_number is at maximum value".to_string(),
+                    )
+                })?;
+        let erc20_tokens = erc20_tokens
+            .unwrap_or_default()
+            .into_iter()
+            .map(|arg| Erc20Token::try_from(arg).map(|token| (token.erc20_contract_address, token)))
+            .collect::<Result<_, _>>()?;
+        let evm_network = match (evm_network, polygon_network) {
+            (Some(evm_network), _) => evm_network,
+            (None, Some(polygon_network)) => polygon_network.evm_network(),
+            (None, None) => {
+                return Err(InvalidStateError::InvalidEvmNetwork(
+                    "either polygon_network or evm_network must be given".to_string(),
+                ))
+            }
+        };
+        let rpc_providers = evm_network.default_providers.clone();
+        let latest_block_confirmations =
+            latest_block_confirmations.unwrap_or(evm_network.finality_depth);
+        let state = Self {
+            evm_network,
+            ecdsa_key_name,
+            icmatic_ledger_id,
+            eth_helper_contract_address,
+            ecdsa_public_key: None,
+            icmatic_minimum_withdrawal_amount,
+            ethereum_block_height,
+            latest_block_confirmations,
+            first_scraped_block_number,
+            last_scraped_block_number,
+            last_erc20_scraped_block_number: last_scraped_block_number,
+            last_observed_block_number: None,
+            events_to_mint: Default::default(),
+            minted_events: Default::default(),
+            invalid_events: Default::default(),
+            reorged_deposits: Default::default(),
+            erc20_tokens,
+            consensus_strategy: consensus_strategy.unwrap_or_default(),
+            rpc_providers,
+            polygon_transactions: PolygonTransactions::new(TransactionNonce::ZERO),
+            skipped_blocks: Default::default(),
+            skipped_block_retries: Default::default(),
+            matic_balance: Default::default(),
+            erc20_balances: Default::default(),
+            pending_withdrawal_principals: Default::default(),
+            active_tasks: Default::default(),
+            http_request_counter: 0,
+            last_transaction_price_estimate: None,
+            provider_disagreements: Default::default(),
+            rpc_cycles_spent: Default::default(),
+            rpc_request_costs: Default::default(),
+            rpc_cost_estimate_fallbacks: Default::default(),
+        };
+        state.validate_config()?;
+        Ok(state)
+    }
+}
@@ -0,0 +1,13 @@
+use candid::{CandidType, Deserialize};
+
+pub mod init;
+pub mod upgrade;
+
+pub use init::InitArg;
+pub use upgrade::UpgradeArg;
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum MinterArg {
+    InitArg(InitArg),
+    UpgradeArg(UpgradeArg),
+}
@@ -0,0 +1,38 @@
+use candid::{CandidType, Deserialize, Nat, Principal};
+use minicbor::{Decode, Encode};
+
+use crate::endpoints::CandidBlockTag;
+use crate::erc20::Erc20TokenArg;
+use crate::rpc_client::ConsensusStrategy;
+use crate::state::audit::{process_event, replay_events};
+use crate::state::event::EventType;
+use crate::state::{mutate_state, STATE};
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq, Eq, Encode, Decode)]
+pub struct UpgradeArg {
+    #[n(0)]
+    pub ecdsa_key_name: Option<String>,
+    #[cbor(n(1), with = "crate::cbor::principal::option")]
+    pub icmatic_ledger_id: Option<Principal>,
+    #[n(2)]
+    pub helper_contract_address: Option<String>,
+    #[cbor(n(3), with = "crate::cbor::nat::option")]
+    pub minimum_withdrawal_amount: Option<Nat>,
+    #[cbor(n(4), with = "crate::cbor::nat::option")]
+    pub last_scraped_block_number: Option<Nat>,
+    #[n(5)]
+    pub ethereum_block_height: Option<CandidBlockTag>,
+    /// ERC-20 tokens to support. Tokens that are already supported may only be given
+    /// again with the same ledger, symbol and decimals.
+    #[n(6)]
+    pub erc20_tokens: Option<Vec<Erc20TokenArg>>,
+    #[n(7)]
+    pub consensus_strategy: Option<ConsensusStrategy>,
+    #[n(8)]
+    pub latest_block_confirmations: Option<u64>,
+}
+
+pub fn post_upgrade(upgrade_arg: Option<UpgradeArg>) {
+    STATE.with(|cell| *cell.borrow_mut() = Some(replay_events()));
+    mutate_state(|s| process_event(s, EventType::Upgrade(upgrade_arg.unwrap_or_default())));
+}
@@ -1,3 +1,4 @@
+use ic_crypto_ecdsa_secp256k1::PublicKey;
 use minicbor::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 use std::fmt;
@@ -5,8 +6,20 @@ use std::fmt::{Debug, Display, Formatter, LowerHex, UpperHex};
 use std::str::FromStr;
 
 /// An Ethereum account address.
-#[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord,Serialize)]
-pub struct Address([u8; 20]);
+#[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Encode, Decode)]
+#[cbor(transparent)]
+pub struct Address(#[cbor(n(0), with = "minicbor::bytes")] [u8; 20]);
+
+/// Derives the Ethereum address of a secp256k1 public key, i.e. the last 20 bytes
+/// of the Keccak-256 hash of the uncompressed key (without the `0x04` prefix).
+pub fn ecdsa_public_key_to_address(public_key: &PublicKey) -> Address {
+    let key_bytes = public_key.serialize_sec1(/*compressed=*/ false);
+    debug_assert_eq!(key_bytes[0], 0x04);
+    let hash = keccak(&key_bytes[1..]);
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash[12..32]);
+    Address::new(address)
+}
 
 impl AsRef<[u8]> for Address {
     fn as_ref(&self) -> &[u8] {
@@ -22,6 +35,12 @@ impl Address {
     }
 }
 
+impl rlp::Encodable for Address {
+    fn rlp_append(&self, s: &mut rlp::RlpStream) {
+        s.append(&self.as_ref());
+    }
+}
+
 impl LowerHex for Address {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "0x{}", hex::encode(self.0))
@@ -1,11 +1,14 @@
 use std::fmt::{Debug, Display, Formatter, LowerHex, UpperHex};
 
 use candid::CandidType;
+use minicbor::{Decode, Encode};
 use serde::Serialize;
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd, CandidType, Serialize)]
-
-pub struct Hash(pub [u8; 32]);
+#[derive(
+    Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd, CandidType, Serialize, Encode, Decode,
+)]
+#[cbor(transparent)]
+pub struct Hash(#[cbor(n(0), with = "minicbor::bytes")] pub [u8; 32]);
 
 impl Debug for Hash {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -0,0 +1,10 @@
+use ic_canister_log::declare_log_buffer;
+
+// High-priority messages.
+declare_log_buffer!(name = INFO, capacity = 1000);
+
+// Low-priority info messages.
+declare_log_buffer!(name = DEBUG, capacity = 1000);
+
+// Trace of HTTP requests and responses.
+declare_log_buffer!(name = TRACE_HTTP, capacity = 1000);
@@ -0,0 +1,119 @@
+use ic_cdk::api::call::RejectionCode;
+use ic_cdk::api::management_canister::ecdsa::{
+    EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgument, EcdsaPublicKeyResponse, SignWithEcdsaArgument,
+};
+use ic_crypto_ecdsa_secp256k1::PublicKey;
+use std::fmt;
+
+/// The derivation path of the minter's key.
+pub const MAIN_DERIVATION_PATH: Vec<Vec<u8>> = vec![];
+
+/// Represents an error from a management canister call, such as
+/// `sign_with_ecdsa` or `ecdsa_public_key`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallError {
+    pub method: String,
+    pub code: RejectionCode,
+    pub message: String,
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "management call '{}' failed: {:?} ({})",
+            self.method, self.code, self.message
+        )
+    }
+}
+
+fn key_id(key_name: String) -> EcdsaKeyId {
+    EcdsaKeyId {
+        curve: EcdsaCurve::Secp256k1,
+        name: key_name,
+    }
+}
+
+/// Fetches the threshold ECDSA public key of this canister.
+pub async fn ecdsa_public_key(
+    key_name: String,
+    derivation_path: Vec<Vec<u8>>,
+) -> Result<EcdsaPublicKeyResponse, CallError> {
+    let (response,) =
+        ic_cdk::api::management_canister::ecdsa::ecdsa_public_key(EcdsaPublicKeyArgument {
+            canister_id: None,
+            derivation_path,
+            key_id: key_id(key_name),
+        })
+        .await
+        .map_err(|(code, message)| CallError {
+            method: "ecdsa_public_key".to_string(),
+            code,
+            message,
+        })?;
+    Ok(response)
+}
+
+/// Signs a message hash using the tECDSA API.
+/// Returns the 64-byte `(r, s)` signature.
+pub async fn sign_with_ecdsa(
+    key_name: String,
+    derivation_path: Vec<Vec<u8>>,
+    message_hash: [u8; 32],
+) -> Result<[u8; 64], CallError> {
+    let (response,) =
+        ic_cdk::api::management_canister::ecdsa::sign_with_ecdsa(SignWithEcdsaArgument {
+            message_hash: message_hash.to_vec(),
+            derivation_path,
+            key_id: key_id(key_name),
+        })
+        .await
+        .map_err(|(code, message)| CallError {
+            method: "sign_with_ecdsa".to_string(),
+            code,
+            message,
+        })?;
+    <[u8; 64]>::try_from(response.signature.as_slice()).map_err(|_| CallError {
+        method: "sign_with_ecdsa".to_string(),
+        code: RejectionCode::CanisterError,
+        message: format!(
+            "expected a 64-byte signature, got {} bytes",
+            response.signature.len()
+        ),
+    })
+}
+
+/// A secp256k1 signature `(r, s)` with the recovery id `v` (0 or 1),
+/// from which the signer's public key can be recovered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecoverableSignature {
+    pub r: ethnum::u256,
+    pub s: ethnum::u256,
+    pub v: u8,
+}
+
+/// Signs a message hash using the tECDSA API and computes the recovery id
+/// of the signature with respect to `public_key`, the key matching `key_name`
+/// and `derivation_path`.
+pub async fn sign_with_ecdsa_recoverable(
+    key_name: String,
+    derivation_path: Vec<Vec<u8>>,
+    public_key: &PublicKey,
+    message_hash: [u8; 32],
+) -> Result<RecoverableSignature, String> {
+    let signature = sign_with_ecdsa(key_name, derivation_path, message_hash)
+        .await
+        .map_err(|e| e.to_string())?;
+    let recovery_id = public_key
+        .try_recovery_from_digest(&message_hash, &signature)
+        .map_err(|e| format!("failed to compute the signature recovery id: {e:?}"))?;
+    if recovery_id.is_x_reduced() {
+        return Err("BUG: the affine x-coordinate of r is reduced".to_string());
+    }
+    let (r, s) = signature.split_at(32);
+    Ok(RecoverableSignature {
+        r: ethnum::u256::from_be_bytes(r.try_into().expect("r is 32 bytes")),
+        s: ethnum::u256::from_be_bytes(s.try_into().expect("s is 32 bytes")),
+        v: u8::from(recovery_id.is_y_odd()),
+    })
+}
@@ -0,0 +1,116 @@
+use icrc_ledger_types::icrc1::transfer::Memo;
+use minicbor::{Decode, Encode};
+
+use crate::events_utils::ReceivedPolygonEvent;
+use crate::log_types::{address::Address, hash::Hash};
+use crate::numeric::{CkTokenAmount, LogIndex};
+use crate::state::transactions::ReimbursementRequest;
+
+/// Memo attached to the icMATIC ledger mint transactions, CBOR-encoded.
+#[derive(Clone, Debug, Eq, PartialEq, Encode, Decode)]
+pub enum MintMemo {
+    #[n(0)]
+    /// The minter received some MATIC.
+    Convert {
+        #[n(0)]
+        /// The sender of the MATIC.
+        from_address: Address,
+        #[n(1)]
+        /// Hash of the transaction that locked the MATIC in the helper contract.
+        tx_hash: Hash,
+        #[n(2)]
+        /// Index of the `TokensLocked` event in the transaction logs.
+        log_index: LogIndex,
+    },
+    #[n(1)]
+    /// The minter failed to process a withdrawal request,
+    /// so no MATIC was sent and the burned icMATIC were reimbursed.
+    ReimburseWithdrawal {
+        #[n(0)]
+        /// The icMATIC burn index identifying the withdrawal request.
+        withdrawal_id: u64,
+    },
+    #[n(2)]
+    /// The transaction of a withdrawal request failed on-chain,
+    /// so the burned icMATIC minus the transaction fee were reimbursed.
+    ReimburseTransaction {
+        #[n(0)]
+        /// The icMATIC burn index identifying the withdrawal request.
+        withdrawal_id: u64,
+        #[n(1)]
+        /// Hash of the failed transaction.
+        tx_hash: Hash,
+    },
+}
+
+/// Memo attached to the icMATIC ledger burn transactions, CBOR-encoded.
+#[derive(Clone, Debug, Eq, PartialEq, Encode, Decode)]
+pub enum BurnMemo {
+    #[n(0)]
+    /// The minter processed a withdrawal request.
+    Convert {
+        #[n(0)]
+        /// The destination of the withdrawal request.
+        to_address: Address,
+    },
+    #[n(1)]
+    /// The minter burned icMATIC to pay the transaction fee of an ERC-20 withdrawal request.
+    Erc20GasFee {
+        #[n(0)]
+        /// The address of the withdrawn token contract.
+        erc20_contract_address: Address,
+        #[n(1)]
+        /// The amount of withdrawn tokens.
+        erc20_withdrawal_amount: CkTokenAmount,
+        #[n(2)]
+        /// The destination of the withdrawal request.
+        to_address: Address,
+    },
+    #[n(2)]
+    /// The minter processed an ERC-20 withdrawal request.
+    Erc20Convert {
+        #[n(0)]
+        /// The icMATIC burn index identifying the withdrawal request.
+        icmatic_withdrawal_id: u64,
+        #[n(1)]
+        /// The destination of the withdrawal request.
+        to_address: Address,
+    },
+}
+
+impl From<MintMemo> for Memo {
+    fn from(memo: MintMemo) -> Self {
+        let bytes = minicbor::to_vec(memo).expect("minicbor serialization should always succeed");
+        Memo::from(bytes)
+    }
+}
+
+impl From<&ReceivedPolygonEvent> for MintMemo {
+    fn from(event: &ReceivedPolygonEvent) -> Self {
+        MintMemo::Convert {
+            from_address: event.from_address,
+            tx_hash: event.transaction_hash,
+            log_index: event.log_index,
+        }
+    }
+}
+
+impl From<&ReimbursementRequest> for MintMemo {
+    fn from(request: &ReimbursementRequest) -> Self {
+        let withdrawal_id = request.withdrawal_id.get();
+        match request.transaction_hash {
+            Some(tx_hash) => MintMemo::ReimburseTransaction {
+                withdrawal_id,
+                tx_hash,
+            },
+            None => MintMemo::ReimburseWithdrawal { withdrawal_id },
+        }
+    }
+}
+
+impl From<BurnMemo> for Memo {
+    fn from(memo: BurnMemo) -> Self {
+        let bytes = minicbor::to_vec(memo).expect("minicbor serialization should always succeed");
+        Memo::from(bytes)
+    }
+}
@@ -4,12 +4,11 @@ use phantom_newtype::Id;
 
 pub enum WeiTag {}
 
-
 pub type Wei = CheckedAmountOf<WeiTag>;
 
 /// Amount of CK token using their smallest denomination.
-// pub enum CkTokenAmountTag {}
-// pub type CkTokenAmount = CheckedAmountOf<CkTokenAmountTag>;
+pub enum CkTokenAmountTag {}
+pub type CkTokenAmount = CheckedAmountOf<CkTokenAmountTag>;
 
 pub enum WeiPerGasUnit {}
 pub type WeiPerGas = CheckedAmountOf<WeiPerGasUnit>;
@@ -43,7 +42,6 @@ pub enum GasUnit {}
 /// The number of gas units attached to a transaction for execution.
 pub type GasAmount = CheckedAmountOf<GasUnit>;
 
-
 pub enum EthLogIndexTag {}
 pub type LogIndex = CheckedAmountOf<EthLogIndexTag>;
 pub enum BurnIndexTag {}
@@ -58,3 +56,11 @@ impl WeiPerGas {
             .map(|value| value.change_units())
     }
 }
+
+impl Wei {
+    /// The highest fee per gas at which `gas` units cost at most this amount.
+    pub fn fee_per_gas(self, gas: GasAmount) -> Option<WeiPerGas> {
+        self.checked_div_floor(gas.into_inner())
+            .map(|value| value.change_units())
+    }
+}
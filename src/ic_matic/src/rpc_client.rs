@@ -0,0 +1,930 @@
+use candid::{CandidType, Deserialize};
+use ic_canister_log::log;
+use ic_cdk::api::call::{CallResult, RejectionCode};
+use minicbor::{Decode, Encode};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::evm_rpc_canister::{
+    Block, BlockTag, EmvRpcService, FeeHistory, FeeHistoryArgs, FeeHistoryResult,
+    GetBlockByNumberResult, GetLogsArgs, GetLogsResult, GetTransactionCountArgs,
+    GetTransactionCountResult, GetTransactionReceiptResult, HttpOutcallError, JsonRpcError,
+    LogEntry, MultiFeeHistoryResult, MultiGetBlockByNumberResult, MultiGetLogsResult,
+    MultiGetTransactionCountResult, MultiGetTransactionReceiptResult,
+    MultiSendRawTransactionResult, RequestCostResult, RequestResult, RpcApi, RpcConfig, RpcError,
+    RpcService, RpcServices, SendRawTransactionResult, SendRawTransactionStatus,
+    TransactionReceipt as EvmTransactionReceipt,
+};
+use crate::log_types::{address::Address, hash::Hash};
+use crate::logs::{INFO, TRACE_HTTP};
+use crate::numeric::{BlockNumber, GasAmount, TransactionCount, WeiPerGas};
+use crate::rpc_providers::{rpc_services, RpcProvider};
+use crate::state::{mutate_state, read_state};
+use crate::tx::{TransactionReceipt, TransactionStatus};
+
+/// Cycles attached to a call to the EVM RPC canister whose cost could not be estimated.
+/// Cycles that are not used by the EVM RPC canister are refunded.
+const RPC_CALL_CYCLES: u128 = 10_000_000_000;
+
+/// Margin added to the estimated cost of a call, in percent, since the cost
+/// of the HTTPS outcalls may change between the estimate and the call.
+const CYCLES_SAFETY_MARGIN_PERCENT: u128 = 20;
+
+/// Time during which the cost of a request computed by the EVM RPC canister is reused.
+const REQUEST_COST_VALIDITY: Duration = Duration::from_secs(60 * 60);
+
+/// Maximum size of the responses, in bytes, which the cost of a call depends on.
+pub const GET_LOGS_RESPONSE_SIZE_ESTIMATE: u64 = 100 * 1024;
+/// The largest response of an HTTPS outcall.
+pub const MAX_RESPONSE_SIZE_ESTIMATE: u64 = 2_000_000;
+const GET_BLOCK_BY_NUMBER_RESPONSE_SIZE_ESTIMATE: u64 = 24 * 1024;
+const FEE_HISTORY_RESPONSE_SIZE_ESTIMATE: u64 = 8 * 1024;
+const GET_TRANSACTION_COUNT_RESPONSE_SIZE_ESTIMATE: u64 = 512;
+const GET_TRANSACTION_RECEIPT_RESPONSE_SIZE_ESTIMATE: u64 = 8 * 1024;
+const GET_TRANSACTION_BY_HASH_RESPONSE_SIZE_ESTIMATE: u64 = 8 * 1024;
+const SEND_RAW_TRANSACTION_RESPONSE_SIZE_ESTIMATE: u64 = 512;
+
+#[derive(Debug)]
+pub enum RpcClientError {
+    /// The inter-canister call to the EVM RPC canister failed.
+    CallRejected {
+        code: RejectionCode,
+        message: String,
+    },
+    /// The providers consistently returned an error.
+    Rpc(RpcError),
+    /// The providers returned results that do not reach a consensus.
+    InconsistentResults,
+    /// The providers returned a result the minter cannot interpret.
+    InvalidResponse(String),
+}
+
+impl RpcClientError {
+    /// Returns true if the response was larger than the response size estimate,
+    /// in which case a larger estimate or a smaller request may succeed.
+    pub fn is_response_too_large(&self) -> bool {
+        matches!(self, RpcClientError::Rpc(error) if is_response_too_large(error))
+    }
+}
+
+/// The IC rejects the HTTPS outcalls whose response exceeds the maximum response size.
+fn is_response_too_large(error: &RpcError) -> bool {
+    matches!(
+        error,
+        RpcError::HttpOutcallError(HttpOutcallError::IcError {
+            code: crate::evm_rpc_canister::RejectionCode::SysFatal,
+            message,
+        }) if message.contains("size limit")
+    )
+}
+
+/// Cost of a request to a provider, as computed by the EVM RPC canister.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestCost {
+    /// Size of the request the cost was computed for. The cost grows with the size
+    /// of the request, so it covers any request that is not larger.
+    pub payload_size: usize,
+    pub cycles: u128,
+    /// Canister time at which the cost was computed.
+    pub computed_at: u64,
+}
+
+/// How the different results returned by the providers are reduced to a single one.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode)]
+pub enum ConsensusStrategy {
+    /// All providers must return the same result.
+    #[default]
+    #[n(0)]
+    Equality,
+    /// At least `min` providers must return the same result.
+    #[n(1)]
+    Threshold {
+        #[n(0)]
+        min: u8,
+    },
+    /// The result returned by the most providers is taken, unless there is a tie.
+    #[n(2)]
+    Majority,
+}
+
+/// Results of a call to the EVM RPC canister, which are either the same for all providers
+/// or given per provider.
+trait MultiRpcResult {
+    type Result: CandidType;
+
+    fn into_results(self) -> Result<Self::Result, Vec<(RpcService, Self::Result)>>;
+
+    /// Separates the successful results from the errors.
+    fn into_ok_or_error(result: Self::Result) -> Result<Self::Result, RpcError>;
+}
+
+macro_rules! impl_multi_rpc_result {
+    ($multi:ident, $single:ident) => {
+        impl MultiRpcResult for $multi {
+            type Result = $single;
+
+            fn into_results(self) -> Result<$single, Vec<(RpcService, $single)>> {
+                match self {
+                    Self::Consistent(result) => Ok(result),
+                    Self::Inconsistent(results) => Err(results),
+                }
+            }
+
+            fn into_ok_or_error(result: $single) -> Result<$single, RpcError> {
+                match result {
+                    $single::Err(error) => Err(error),
+                    ok => Ok(ok),
+                }
+            }
+        }
+    };
+}
+
+impl_multi_rpc_result!(MultiGetLogsResult, GetLogsResult);
+impl_multi_rpc_result!(MultiGetBlockByNumberResult, GetBlockByNumberResult);
+impl_multi_rpc_result!(MultiFeeHistoryResult, FeeHistoryResult);
+impl_multi_rpc_result!(MultiGetTransactionCountResult, GetTransactionCountResult);
+impl_multi_rpc_result!(
+    MultiGetTransactionReceiptResult,
+    GetTransactionReceiptResult
+);
+impl_multi_rpc_result!(MultiSendRawTransactionResult, SendRawTransactionResult);
+
+/// Input of a transaction as returned by a single provider, `None` if the transaction is unknown.
+#[derive(CandidType)]
+enum TransactionInputResult {
+    Ok(Option<String>),
+    Err(RpcError),
+}
+
+/// Inputs of a transaction as returned by each provider, which are queried one at a time.
+struct TransactionInputResults(Vec<(RpcService, TransactionInputResult)>);
+
+impl MultiRpcResult for TransactionInputResults {
+    type Result = TransactionInputResult;
+
+    fn into_results(
+        self,
+    ) -> Result<TransactionInputResult, Vec<(RpcService, TransactionInputResult)>> {
+        Err(self.0)
+    }
+
+    fn into_ok_or_error(
+        result: TransactionInputResult,
+    ) -> Result<TransactionInputResult, RpcError> {
+        match result {
+            TransactionInputResult::Err(error) => Err(error),
+            ok => Ok(ok),
+        }
+    }
+}
+
+fn configured_providers() -> Vec<RpcProvider> {
+    read_state(|s| s.rpc_providers.clone())
+}
+
+fn consensus_strategy() -> ConsensusStrategy {
+    read_state(|s| s.consensus_strategy)
+}
+
+/// Reduces the results of the providers to a single one with the given strategy.
+/// The providers whose result differs from the agreed one are recorded.
+fn reduce<R: MultiRpcResult>(
+    request_id: u64,
+    strategy: ConsensusStrategy,
+    result: R,
+) -> Result<R::Result, RpcClientError> {
+    let results = match result.into_results() {
+        Ok(result) => return Ok(result),
+        Err(results) => results,
+    };
+    let (result, dissenters) = reduce_results::<R>(request_id, strategy, results);
+    if !dissenters.is_empty() {
+        mutate_state(|s| {
+            for provider in dissenters {
+                *s.provider_disagreements.entry(provider).or_default() += 1;
+            }
+        });
+    }
+    result
+}
+
+/// Reduces the results of the providers to a single one with the given strategy and returns
+/// the names of the providers that disagree with it.
+/// Results are identical if their Candid encodings are. Only successful results are votes:
+/// an error is returned when no successful result gets enough of them.
+fn reduce_results<R: MultiRpcResult>(
+    request_id: u64,
+    strategy: ConsensusStrategy,
+    results: Vec<(RpcService, R::Result)>,
+) -> (Result<R::Result, RpcClientError>, Vec<String>) {
+    let total = results.len();
+    let mut votes: BTreeMap<Vec<u8>, (R::Result, Vec<RpcService>)> = BTreeMap::new();
+    let mut errors: Vec<(RpcService, RpcError)> = Vec::new();
+    for (provider, result) in results {
+        let result = match R::into_ok_or_error(result) {
+            Ok(result) => result,
+            Err(error) => {
+                errors.push((provider, error));
+                continue;
+            }
+        };
+        let key = candid::encode_one(&result).expect("BUG: failed to encode an RPC result");
+        votes
+            .entry(key)
+            .or_insert_with(|| (result, Vec::new()))
+            .1
+            .push(provider);
+    }
+    let mut votes: Vec<_> = votes.into_values().collect();
+    votes.sort_by_key(|(_, providers)| std::cmp::Reverse(providers.len()));
+    let most_votes = votes.first().map_or(0, |(_, providers)| providers.len());
+    let is_tie = votes.get(1).map(|(_, providers)| providers.len()) == Some(most_votes);
+    let mut dissenters = Vec::new();
+    if most_votes > 0 && !is_tie && most_votes < total {
+        dissenters = votes[1..]
+            .iter()
+            .flat_map(|(_, providers)| providers.iter())
+            .chain(errors.iter().map(|(provider, _)| provider))
+            .map(provider_name)
+            .collect();
+        log!(
+            INFO,
+            "[{request_id}] providers {dissenters:?} disagree with {most_votes} of {total} providers"
+        );
+    }
+    let min_votes = match strategy {
+        ConsensusStrategy::Equality => total,
+        ConsensusStrategy::Threshold { min } => min as usize,
+        ConsensusStrategy::Majority => 1,
+    };
+    if most_votes == 0 || is_tie || most_votes < min_votes {
+        log!(
+            INFO,
+            "[{request_id}] no consensus with {strategy:?}: at most {most_votes} of {total} providers agree"
+        );
+        let mut errors: Vec<_> = errors.into_iter().map(|(_, error)| error).collect();
+        // Providers whose response is too large fail with different messages,
+        // e.g. when they returned responses of different sizes.
+        let error = match response_too_large(&mut errors) {
+            Some(error) => RpcClientError::Rpc(error),
+            None if votes.is_empty() && !errors.is_empty() => {
+                RpcClientError::Rpc(errors.swap_remove(0))
+            }
+            None => RpcClientError::InconsistentResults,
+        };
+        return (Err(error), dissenters);
+    }
+    (Ok(votes.swap_remove(0).0), dissenters)
+}
+
+/// Removes and returns the first of the given errors reporting a response that was too large.
+fn response_too_large(errors: &mut Vec<RpcError>) -> Option<RpcError> {
+    errors
+        .iter()
+        .position(is_response_too_large)
+        .map(|index| errors.swap_remove(index))
+}
+
+/// Name of a provider in logs and metrics, which leaves out the headers of custom
+/// providers since they may contain API keys.
+fn provider_name(provider: &RpcService) -> String {
+    match provider {
+        RpcService::EthSepolia(service) => format!("EthSepolia({service:?})"),
+        RpcService::BaseMainnet(service) => format!("BaseMainnet({service:?})"),
+        RpcService::Custom(api) => format!("Custom({})", api.url),
+        RpcService::OptimismMainnet(service) => format!("OptimismMainnet({service:?})"),
+        RpcService::ArbitrumOne(service) => format!("ArbitrumOne({service:?})"),
+        RpcService::EthMainnet(service) => format!("EthMainnet({service:?})"),
+        RpcService::Chain(chain_id) => format!("Chain({chain_id})"),
+        RpcService::Provider(provider_id) => format!("Provider({provider_id})"),
+    }
+}
+
+impl From<BlockNumber> for BlockTag {
+    fn from(block_number: BlockNumber) -> Self {
+        BlockTag::Number(block_number.into_inner().as_u128())
+    }
+}
+
+impl TryFrom<EvmTransactionReceipt> for TransactionReceipt {
+    type Error = String;
+
+    fn try_from(receipt: EvmTransactionReceipt) -> Result<Self, Self::Error> {
+        let status = match receipt.status {
+            0 => TransactionStatus::Failure,
+            1 => TransactionStatus::Success,
+            status => return Err(format!("unknown transaction status {status}")),
+        };
+        Ok(Self {
+            block_hash: receipt.blockHash.parse()?,
+            block_number: BlockNumber::from(receipt.blockNumber),
+            effective_gas_price: WeiPerGas::from(receipt.effectiveGasPrice),
+            gas_used: GasAmount::from(receipt.gasUsed),
+            status,
+            transaction_hash: receipt.transactionHash.parse()?,
+        })
+    }
+}
+
+pub struct RpcClient;
+
+impl RpcClient {
+    /// Returns the log entries matching `args`, which must fit in `response_size_estimate` bytes,
+    /// as returned by the given providers.
+    pub async fn get_logs(
+        &self,
+        providers: Vec<RpcProvider>,
+        args: GetLogsArgs,
+        response_size_estimate: u64,
+    ) -> Result<Vec<LogEntry>, RpcClientError> {
+        let request_id = next_request_id();
+        log!(
+            TRACE_HTTP,
+            "[{request_id}] eth_getLogs for addresses {:?} from {:?} to {:?}",
+            args.addresses,
+            args.fromBlock,
+            args.toBlock
+        );
+        let params = json!([{
+            "fromBlock": args.fromBlock.as_ref().map(block_tag_param),
+            "toBlock": args.toBlock.as_ref().map(block_tag_param),
+            "address": args.addresses,
+            "topics": args.topics,
+        }]);
+        let result = call_evm_rpc(
+            request_id,
+            providers,
+            "eth_getLogs",
+            params,
+            response_size_estimate,
+            |services, config, cycles| async move {
+                EmvRpcService
+                    .eth_get_logs(services, config, args, cycles)
+                    .await
+            },
+        )
+        .await?;
+        match reduce(request_id, consensus_strategy(), result)? {
+            GetLogsResult::Ok(entries) => {
+                log!(
+                    TRACE_HTTP,
+                    "[{request_id}] received {} log entries",
+                    entries.len()
+                );
+                Ok(entries)
+            }
+            GetLogsResult::Err(error) => Err(RpcClientError::Rpc(error)),
+        }
+    }
+
+    pub async fn get_block_by_number(&self, block: BlockTag) -> Result<Block, RpcClientError> {
+        let request_id = next_request_id();
+        log!(TRACE_HTTP, "[{request_id}] eth_getBlockByNumber {block:?}");
+        let result = call_evm_rpc(
+            request_id,
+            configured_providers(),
+            "eth_getBlockByNumber",
+            json!([block_tag_param(&block), false]),
+            GET_BLOCK_BY_NUMBER_RESPONSE_SIZE_ESTIMATE,
+            |services, config, cycles| async move {
+                EmvRpcService
+                    .eth_get_block_by_number(services, config, block, cycles)
+                    .await
+            },
+        )
+        .await?;
+        match reduce(request_id, consensus_strategy(), result)? {
+            GetBlockByNumberResult::Ok(block) => {
+                log!(TRACE_HTTP, "[{request_id}] received block {}", block.number);
+                Ok(block)
+            }
+            GetBlockByNumberResult::Err(error) => Err(RpcClientError::Rpc(error)),
+        }
+    }
+
+    pub async fn fee_history(
+        &self,
+        args: FeeHistoryArgs,
+    ) -> Result<Option<FeeHistory>, RpcClientError> {
+        let request_id = next_request_id();
+        log!(TRACE_HTTP, "[{request_id}] eth_feeHistory {args:?}");
+        let params = json!([
+            format!("{:#x}", args.blockCount),
+            block_tag_param(&args.newestBlock),
+            args.rewardPercentiles
+                .as_ref()
+                .map(|percentiles| percentiles.to_vec()),
+        ]);
+        let result = call_evm_rpc(
+            request_id,
+            configured_providers(),
+            "eth_feeHistory",
+            params,
+            FEE_HISTORY_RESPONSE_SIZE_ESTIMATE,
+            |services, config, cycles| async move {
+                EmvRpcService
+                    .eth_fee_history(services, config, args, cycles)
+                    .await
+            },
+        )
+        .await?;
+        match reduce(request_id, consensus_strategy(), result)? {
+            FeeHistoryResult::Ok(fee_history) => {
+                log!(TRACE_HTTP, "[{request_id}] received {fee_history:?}");
+                Ok(fee_history)
+            }
+            FeeHistoryResult::Err(error) => Err(RpcClientError::Rpc(error)),
+        }
+    }
+
+    pub async fn get_transaction_count(
+        &self,
+        address: Address,
+        block: BlockTag,
+    ) -> Result<TransactionCount, RpcClientError> {
+        let request_id = next_request_id();
+        log!(
+            TRACE_HTTP,
+            "[{request_id}] eth_getTransactionCount for {address} at {block:?}"
+        );
+        let args = GetTransactionCountArgs {
+            address: format!("{address:x}"),
+            block,
+        };
+        let result = call_evm_rpc(
+            request_id,
+            configured_providers(),
+            "eth_getTransactionCount",
+            json!([args.address, block_tag_param(&args.block)]),
+            GET_TRANSACTION_COUNT_RESPONSE_SIZE_ESTIMATE,
+            |services, config, cycles| async move {
+                EmvRpcService
+                    .eth_get_transaction_count(services, config, args, cycles)
+                    .await
+            },
+        )
+        .await?;
+        match reduce(request_id, consensus_strategy(), result)? {
+            GetTransactionCountResult::Ok(count) => {
+                log!(TRACE_HTTP, "[{request_id}] received count {count}");
+                Ok(TransactionCount::from(count))
+            }
+            GetTransactionCountResult::Err(error) => Err(RpcClientError::Rpc(error)),
+        }
+    }
+
+    /// Returns the receipt of the transaction, or `None` if it is not mined.
+    pub async fn get_transaction_receipt(
+        &self,
+        hash: Hash,
+    ) -> Result<Option<TransactionReceipt>, RpcClientError> {
+        let request_id = next_request_id();
+        log!(
+            TRACE_HTTP,
+            "[{request_id}] eth_getTransactionReceipt {hash}"
+        );
+        let result = call_evm_rpc(
+            request_id,
+            configured_providers(),
+            "eth_getTransactionReceipt",
+            json!([hash.to_string()]),
+            GET_TRANSACTION_RECEIPT_RESPONSE_SIZE_ESTIMATE,
+            |services, config, cycles| async move {
+                EmvRpcService
+                    .eth_get_transaction_receipt(services, config, hash.to_string(), cycles)
+                    .await
+            },
+        )
+        .await?;
+        match reduce(request_id, consensus_strategy(), result)? {
+            GetTransactionReceiptResult::Ok(receipt) => {
+                let receipt = receipt
+                    .map(TransactionReceipt::try_from)
+                    .transpose()
+                    .map_err(RpcClientError::InvalidResponse)?;
+                log!(TRACE_HTTP, "[{request_id}] received receipt {receipt:?}");
+                Ok(receipt)
+            }
+            GetTransactionReceiptResult::Err(error) => Err(RpcClientError::Rpc(error)),
+        }
+    }
+
+    pub async fn send_raw_transaction(
+        &self,
+        raw_signed_transaction_hex: String,
+    ) -> Result<SendRawTransactionStatus, RpcClientError> {
+        let request_id = next_request_id();
+        log!(
+            TRACE_HTTP,
+            "[{request_id}] eth_sendRawTransaction {raw_signed_transaction_hex}"
+        );
+        let result = call_evm_rpc(
+            request_id,
+            configured_providers(),
+            "eth_sendRawTransaction",
+            json!([raw_signed_transaction_hex]),
+            SEND_RAW_TRANSACTION_RESPONSE_SIZE_ESTIMATE,
+            |services, config, cycles| async move {
+                EmvRpcService
+                    .eth_send_raw_transaction(services, config, raw_signed_transaction_hex, cycles)
+                    .await
+            },
+        )
+        .await?;
+        // A provider that already received the transaction, e.g. from another provider,
+        // rejects it with `NonceTooLow`: the transaction was sent as soon as one provider
+        // accepted it. Otherwise the statuses are reduced like any other result.
+        let result = match result {
+            MultiSendRawTransactionResult::Inconsistent(mut results) => {
+                match results.iter().position(|(_, result)| {
+                    matches!(
+                        result,
+                        SendRawTransactionResult::Ok(SendRawTransactionStatus::Ok(_))
+                    )
+                }) {
+                    Some(index) => {
+                        MultiSendRawTransactionResult::Consistent(results.swap_remove(index).1)
+                    }
+                    None => MultiSendRawTransactionResult::Inconsistent(results),
+                }
+            }
+            consistent => consistent,
+        };
+        match reduce(request_id, consensus_strategy(), result)? {
+            SendRawTransactionResult::Ok(status) => {
+                log!(TRACE_HTTP, "[{request_id}] received status {status:?}");
+                Ok(status)
+            }
+            SendRawTransactionResult::Err(error) => Err(RpcClientError::Rpc(error)),
+        }
+    }
+
+    /// Returns the input of the transaction, or `None` if it is unknown to the providers.
+    /// The EVM RPC canister has no `eth_getTransactionByHash` endpoint, so the JSON-RPC request
+    /// is sent to each provider separately and the inputs they return are compared.
+    pub async fn get_transaction_input(
+        &self,
+        providers: Vec<RpcProvider>,
+        hash: Hash,
+    ) -> Result<Option<Vec<u8>>, RpcClientError> {
+        let request_id = next_request_id();
+        log!(TRACE_HTTP, "[{request_id}] eth_getTransactionByHash {hash}");
+        let params = json!([hash.to_string()]);
+        let payload = json!({
+            "jsonrpc": "2.0",
+            "method": "eth_getTransactionByHash",
+            "params": params,
+            "id": request_id,
+        })
+        .to_string();
+        let results = futures::future::join_all(providers.into_iter().map(|provider| {
+            let service = RpcService::Custom(RpcApi::from(&provider));
+            let params = params.clone();
+            let payload = payload.clone();
+            async move {
+                let result = call_evm_rpc(
+                    request_id,
+                    vec![provider],
+                    "eth_getTransactionByHash",
+                    params,
+                    GET_TRANSACTION_BY_HASH_RESPONSE_SIZE_ESTIMATE,
+                    |_services, _config, cycles| async move {
+                        EmvRpcService
+                            .request(
+                                service,
+                                payload,
+                                GET_TRANSACTION_BY_HASH_RESPONSE_SIZE_ESTIMATE,
+                                cycles,
+                            )
+                            .await
+                    },
+                )
+                .await?;
+                Ok((
+                    RpcService::Custom(RpcApi::from(&provider)),
+                    transaction_input(result),
+                ))
+            }
+        }))
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, RpcClientError>>()?;
+        match reduce(
+            request_id,
+            consensus_strategy(),
+            TransactionInputResults(results),
+        )? {
+            TransactionInputResult::Ok(input) => {
+                log!(TRACE_HTTP, "[{request_id}] received input {input:?}");
+                input
+                    .map(|input| {
+                        hex::decode(input.strip_prefix("0x").unwrap_or(&input)).map_err(|e| {
+                            RpcClientError::InvalidResponse(format!(
+                                "invalid transaction input {input}: {e}"
+                            ))
+                        })
+                    })
+                    .transpose()
+            }
+            TransactionInputResult::Err(error) => Err(RpcClientError::Rpc(error)),
+        }
+    }
+}
+
+/// Extracts the input of the transaction from a raw response to `eth_getTransactionByHash`.
+/// Only the input is kept, since providers format the rest of their responses differently.
+fn transaction_input(result: RequestResult) -> TransactionInputResult {
+    let response = match result {
+        RequestResult::Ok(response) => response,
+        RequestResult::Err(error) => return TransactionInputResult::Err(error),
+    };
+    let invalid_response = |reason: String| {
+        TransactionInputResult::Err(RpcError::HttpOutcallError(
+            HttpOutcallError::InvalidHttpJsonRpcResponse {
+                status: 200,
+                body: response.clone(),
+                parsingError: Some(reason),
+            },
+        ))
+    };
+    let value: serde_json::Value = match serde_json::from_str(&response) {
+        Ok(value) => value,
+        Err(e) => return invalid_response(e.to_string()),
+    };
+    if let Some(error) = value.get("error") {
+        return TransactionInputResult::Err(RpcError::JsonRpcError(JsonRpcError {
+            code: error["code"].as_i64().unwrap_or_default(),
+            message: error["message"].as_str().unwrap_or_default().to_string(),
+        }));
+    }
+    match value.get("result") {
+        Some(serde_json::Value::Null) => TransactionInputResult::Ok(None),
+        Some(transaction) => match transaction["input"].as_str() {
+            Some(input) => TransactionInputResult::Ok(Some(input.to_lowercase())),
+            None => invalid_response("missing transaction input".to_string()),
+        },
+        None => invalid_response("missing result".to_string()),
+    }
+}
+
+/// Calls the EVM RPC canister with the estimated cost of the request attached,
+/// and records the cycles it actually charged.
+async fn call_evm_rpc<R, F, Fut>(
+    request_id: u64,
+    providers: Vec<RpcProvider>,
+    method: &str,
+    params: serde_json::Value,
+    response_size_estimate: u64,
+    call: F,
+) -> Result<R, RpcClientError>
+where
+    F: FnOnce(RpcServices, Option<RpcConfig>, u128) -> Fut,
+    Fut: Future<Output = CallResult<(R,)>>,
+{
+    let cycles = estimate_cycles(
+        request_id,
+        &providers,
+        method,
+        params,
+        response_size_estimate,
+    )
+    .await;
+    let config = RpcConfig {
+        responseSizeEstimate: Some(response_size_estimate),
+    };
+    let result = call(rpc_services(&providers), Some(config), cycles).await;
+    let refunded = ic_cdk::api::call::msg_cycles_refunded128();
+    let spent = cycles.saturating_sub(refunded);
+    log!(
+        TRACE_HTTP,
+        "[{request_id}] {method} attached {cycles} cycles, {refunded} refunded"
+    );
+    mutate_state(|s| {
+        let total = s.rpc_cycles_spent.entry(method.to_string()).or_default();
+        *total = total.saturating_add(spent);
+    });
+    let (result,) =
+        result.map_err(|(code, message)| RpcClientError::CallRejected { code, message })?;
+    Ok(result)
+}
+
+/// Estimates the cycles to attach to a request: the sum of its cost at each provider,
+/// as computed by the EVM RPC canister, plus a safety margin. The costs are reused for
+/// [`REQUEST_COST_VALIDITY`] for requests of the same method that are not larger.
+/// Falls back to [`RPC_CALL_CYCLES`] if the cost is unknown.
+async fn estimate_cycles(
+    request_id: u64,
+    providers: &[RpcProvider],
+    method: &str,
+    params: serde_json::Value,
+    response_size_estimate: u64,
+) -> u128 {
+    let payload = json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": params,
+        "id": request_id,
+    })
+    .to_string();
+    let now = ic_cdk::api::time();
+    let cost_key = |provider: &RpcProvider| {
+        (
+            method.to_string(),
+            provider.url.clone(),
+            response_size_estimate,
+        )
+    };
+    let mut total_cost: u128 = 0;
+    let mut unknown_costs = Vec::new();
+    read_state(|s| {
+        for provider in providers {
+            match s.rpc_request_costs.get(&cost_key(provider)) {
+                Some(cost)
+                    if cost.payload_size >= payload.len()
+                        && now.saturating_sub(cost.computed_at)
+                            < REQUEST_COST_VALIDITY.as_nanos() as u64 =>
+                {
+                    total_cost = total_cost.saturating_add(cost.cycles)
+                }
+                _ => unknown_costs.push(provider),
+            }
+        }
+    });
+    let costs = futures::future::join_all(unknown_costs.iter().map(|provider| {
+        let service = RpcService::Custom(RpcApi::from(*provider));
+        let payload = payload.clone();
+        async move {
+            EmvRpcService
+                .request_cost(service, payload, response_size_estimate)
+                .await
+        }
+    }))
+    .await;
+    for (provider, cost) in unknown_costs.into_iter().zip(costs) {
+        match cost {
+            Ok((RequestCostResult::Ok(cost),)) => {
+                total_cost = total_cost.saturating_add(cost);
+                mutate_state(|s| {
+                    s.rpc_request_costs.insert(
+                        cost_key(provider),
+                        RequestCost {
+                            payload_size: payload.len(),
+                            cycles: cost,
+                            computed_at: now,
+                        },
+                    )
+                });
+            }
+            error => {
+                log!(
+                    INFO,
+                    "[{request_id}] failed to estimate the cost of {method}: {error:?}"
+                );
+                mutate_state(|s| {
+                    *s.rpc_cost_estimate_fallbacks
+                        .entry(method.to_string())
+                        .or_default() += 1
+                });
+                return RPC_CALL_CYCLES;
+            }
+        }
+    }
+    total_cost.saturating_mul(100 + CYCLES_SAFETY_MARGIN_PERCENT) / 100
+}
+
+/// JSON-RPC representation of a block tag.
+fn block_tag_param(block: &BlockTag) -> String {
+    match block {
+        BlockTag::Earliest => "earliest".to_string(),
+        BlockTag::Safe => "safe".to_string(),
+        BlockTag::Finalized => "finalized".to_string(),
+        BlockTag::Latest => "latest".to_string(),
+        BlockTag::Number(number) => format!("{number:#x}"),
+        BlockTag::Pending => "pending".to_string(),
+    }
+}
+
+fn next_request_id() -> u64 {
+    mutate_state(|s| {
+        let request_id = s.http_request_counter;
+        s.http_request_counter = s.http_request_counter.wrapping_add(1);
+        request_id
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm_rpc_canister::RejectionCode as EvmRejectionCode;
+
+    fn ic_error(code: EvmRejectionCode, message: &str) -> RpcError {
+        RpcError::HttpOutcallError(HttpOutcallError::IcError {
+            code,
+            message: message.to_string(),
+        })
+    }
+
+    fn provider(url: &str) -> RpcService {
+        RpcService::Custom(RpcApi {
+            url: url.to_string(),
+            headers: None,
+        })
+    }
+
+    fn logs_and_two_timeouts() -> Vec<(RpcService, GetLogsResult)> {
+        let timeout =
+            || GetLogsResult::Err(ic_error(EvmRejectionCode::SysTransient, "Timeout expired"));
+        vec![
+            (provider("https://a"), GetLogsResult::Ok(vec![])),
+            (provider("https://b"), timeout()),
+            (provider("https://c"), timeout()),
+        ]
+    }
+
+    #[test]
+    fn should_report_response_too_large_when_providers_disagree() {
+        let mut errors = vec![
+            ic_error(EvmRejectionCode::SysTransient, "Timeout expired"),
+            ic_error(
+                EvmRejectionCode::SysFatal,
+                "Http body exceeds size limit of 102400 bytes.",
+            ),
+            ic_error(
+                EvmRejectionCode::SysFatal,
+                "Header size exceeds specified response size limit 102400",
+            ),
+        ];
+
+        let error = response_too_large(&mut errors).expect("expected a response too large error");
+
+        assert!(RpcClientError::Rpc(error).is_response_too_large());
+    }
+
+    #[test]
+    fn should_not_report_other_errors_as_response_too_large() {
+        let mut errors = vec![
+            ic_error(EvmRejectionCode::SysTransient, "Timeout expired"),
+            ic_error(EvmRejectionCode::SysFatal, "Connection refused"),
+        ];
+
+        assert!(response_too_large(&mut errors).is_none());
+        assert!(!RpcClientError::InconsistentResults.is_response_too_large());
+    }
+
+    #[test]
+    fn should_not_count_errors_as_votes() {
+        let (result, dissenters) = reduce_results::<MultiGetLogsResult>(
+            1,
+            ConsensusStrategy::Majority,
+            logs_and_two_timeouts(),
+        );
+
+        assert!(matches!(result, Ok(GetLogsResult::Ok(entries)) if entries.is_empty()));
+        assert_eq!(dissenters, vec!["Custom(https://b)", "Custom(https://c)"]);
+    }
+
+    #[test]
+    fn should_return_an_error_when_no_result_reaches_the_threshold() {
+        let (result, _dissenters) = reduce_results::<MultiGetLogsResult>(
+            1,
+            ConsensusStrategy::Threshold { min: 2 },
+            logs_and_two_timeouts(),
+        );
+
+        assert!(matches!(result, Err(RpcClientError::InconsistentResults)));
+    }
+
+    #[test]
+    fn should_compare_only_transaction_inputs() {
+        let first = RequestResult::Ok(
+            r#"{"jsonrpc":"2.0","id":1,"result":{"hash":"0xa8c7","input":"0x90624A42","v":"0x1"}}"#
+                .to_string(),
+        );
+        let second = RequestResult::Ok(
+            r#"{"id":1,"jsonrpc":"2.0","result":{"v":"0x01","input":"0x90624a42","hash":"0xA8C7"}}"#
+                .to_string(),
+        );
+
+        let first = candid::encode_one(transaction_input(first)).unwrap();
+        let second = candid::encode_one(transaction_input(second)).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(
+            first,
+            candid::encode_one(TransactionInputResult::Ok(Some("0x90624a42".to_string()))).unwrap()
+        );
+    }
+
+    #[test]
+    fn should_parse_unknown_transaction() {
+        let result = RequestResult::Ok(r#"{"jsonrpc":"2.0","id":1,"result":null}"#.to_string());
+
+        assert!(matches!(
+            transaction_input(result),
+            TransactionInputResult::Ok(None)
+        ));
+    }
+}
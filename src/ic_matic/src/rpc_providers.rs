@@ -1,17 +1,155 @@
+use candid::{CandidType, Deserialize};
+use minicbor::{Decode, Encode};
 use std::fmt::{Display, Formatter};
+use std::time::Duration;
 
+use crate::evm_rpc_canister::{HttpHeader, RpcApi, RpcServices};
+use crate::state::{read_state, InvalidStateError};
+
+/// Maximum number of JSON-RPC providers queried for each request.
+pub const MAX_RPC_PROVIDERS: usize = 8;
+
+/// A JSON-RPC provider of the Polygon network, which the EVM RPC canister
+/// has no built-in service for.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct RpcProvider {
+    /// The HTTPS endpoint of the provider.
+    #[n(0)]
+    pub url: String,
+    /// Headers sent with every request, e.g. to authenticate.
+    #[n(1)]
+    pub headers: Option<Vec<RpcHeader>>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct RpcHeader {
+    #[n(0)]
+    pub name: String,
+    #[n(1)]
+    pub value: String,
+}
+
+impl RpcProvider {
+    fn public(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            headers: None,
+        }
+    }
+}
+
+impl From<&RpcProvider> for RpcApi {
+    fn from(provider: &RpcProvider) -> Self {
+        Self {
+            url: provider.url.clone(),
+            headers: provider.headers.as_ref().map(|headers| {
+                headers
+                    .iter()
+                    .map(|header| HttpHeader {
+                        name: header.name.clone(),
+                        value: header.value.clone(),
+                    })
+                    .collect()
+            }),
+        }
+    }
+}
+
+/// Checks that the given providers can be queried: there is at least one
+/// and at most [`MAX_RPC_PROVIDERS`] of them, all distinct and reached over HTTPS.
+pub fn validate_rpc_providers(providers: &[RpcProvider]) -> Result<(), InvalidStateError> {
+    if providers.is_empty() || providers.len() > MAX_RPC_PROVIDERS {
+        return Err(InvalidStateError::InvalidRpcProviders(format!(
+            "expected between 1 and {MAX_RPC_PROVIDERS} providers, got {}",
+            providers.len()
+        )));
+    }
+    for (i, provider) in providers.iter().enumerate() {
+        if !provider.url.starts_with("https://") {
+            return Err(InvalidStateError::InvalidRpcProviders(format!(
+                "provider URL {} must use HTTPS",
+                provider.url
+            )));
+        }
+        if providers[..i].iter().any(|other| other.url == provider.url) {
+            return Err(InvalidStateError::InvalidRpcProviders(format!(
+                "duplicate provider URL {}",
+                provider.url
+            )));
+        }
+        if provider
+            .headers
+            .iter()
+            .flatten()
+            .any(|header| header.name.trim().is_empty())
+        {
+            return Err(InvalidStateError::InvalidRpcProviders(format!(
+                "provider {} has a header with a blank name",
+                provider.url
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The given JSON-RPC providers, as queried through the EVM RPC canister.
+pub fn rpc_services(providers: &[RpcProvider]) -> RpcServices {
+    RpcServices::Custom {
+        chainId: read_state(|s| s.evm_network.chain_id),
+        services: providers.iter().map(RpcApi::from).collect(),
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Encode, Decode)]
+#[cbor(index_only)]
 pub enum PolygonNetwork {
+    #[n(0)]
     Mainnet,
+    #[n(1)]
     Amoy,
 }
 
 impl PolygonNetwork {
     pub fn chain_id(&self) -> u64 {
         match self {
-            PolygonNetwork::Mainnet => 1,
+            PolygonNetwork::Mainnet => 137,
             PolygonNetwork::Amoy => 80002,
         }
     }
+
+    /// The description of the network, with public JSON-RPC providers.
+    pub fn evm_network(&self) -> EvmNetwork {
+        match self {
+            PolygonNetwork::Mainnet => EvmNetwork {
+                name: self.to_string(),
+                chain_id: self.chain_id(),
+                // Reorganizations deeper than 128 blocks happened on Polygon PoS (157 blocks
+                // in February 2023), keep a margin above the deepest one observed.
+                finality_depth: 256,
+                block_time_secs: 2,
+                native_symbol: "POL".to_string(),
+                default_providers: vec![
+                    RpcProvider::public("https://polygon-rpc.com"),
+                    RpcProvider::public("https://polygon-bor-rpc.publicnode.com"),
+                    RpcProvider::public("https://rpc.ankr.com/polygon"),
+                ],
+            },
+            PolygonNetwork::Amoy => EvmNetwork {
+                name: self.to_string(),
+                chain_id: self.chain_id(),
+                // Test tokens have no value: a shallower depth keeps deposits quick to test
+                // while still covering the common short reorganizations.
+                finality_depth: 32,
+                block_time_secs: 2,
+                native_symbol: "POL".to_string(),
+                default_providers: vec![
+                    RpcProvider::public("https://rpc-amoy.polygon.technology"),
+                    RpcProvider::public("https://polygon-amoy-bor-rpc.publicnode.com"),
+                    RpcProvider::public("https://rpc.ankr.com/polygon_amoy"),
+                ],
+            },
+        }
+    }
 }
 
 impl TryFrom<u64> for PolygonNetwork {
@@ -19,9 +157,9 @@ impl TryFrom<u64> for PolygonNetwork {
 
     fn try_from(value: u64) -> Result<Self, Self::Error> {
         match value {
-            1 => Ok(PolygonNetwork::Mainnet),
+            137 => Ok(PolygonNetwork::Mainnet),
             80002 => Ok(PolygonNetwork::Amoy),
-            _ => Err("Unknown Ethereum Network".to_string()),
+            _ => Err(format!("Unknown Polygon network with chain id {value}")),
         }
     }
 }
@@ -29,8 +167,67 @@ impl TryFrom<u64> for PolygonNetwork {
 impl Display for PolygonNetwork {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            PolygonNetwork::Mainnet => write!(f, "Ethereum Mainnet"),
-            PolygonNetwork::Amoy => write!(f, "Ethereum Testnet Sepolia"),
+            PolygonNetwork::Mainnet => write!(f, "Polygon Mainnet"),
+            PolygonNetwork::Amoy => write!(f, "Polygon Testnet Amoy"),
+        }
+    }
+}
+
+/// An EVM chain the minter can target, e.g. Polygon PoS, Polygon zkEVM or any
+/// other chain whose JSON-RPC providers the EVM RPC canister can reach.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct EvmNetwork {
+    #[n(0)]
+    pub name: String,
+    #[n(1)]
+    pub chain_id: u64,
+    /// Number of blocks on top of a block after which it is not expected to be reorganized.
+    #[n(2)]
+    pub finality_depth: u64,
+    /// Average time between two blocks.
+    #[n(3)]
+    pub block_time_secs: u64,
+    /// Symbol of the native currency paying for gas.
+    #[n(4)]
+    pub native_symbol: String,
+    /// JSON-RPC providers used until controllers configure others.
+    #[n(5)]
+    pub default_providers: Vec<RpcProvider>,
+}
+
+impl EvmNetwork {
+    pub fn validate(&self) -> Result<(), InvalidStateError> {
+        if self.name.trim().is_empty() || self.native_symbol.trim().is_empty() {
+            return Err(InvalidStateError::InvalidEvmNetwork(
+                "name and native_symbol cannot be blank".to_string(),
+            ));
+        }
+        if self.chain_id == 0 {
+            return Err(InvalidStateError::InvalidEvmNetwork(
+                "chain_id must be positive".to_string(),
+            ));
+        }
+        if self.finality_depth == 0 || self.block_time_secs == 0 {
+            return Err(InvalidStateError::InvalidEvmNetwork(
+                "finality_depth and block_time_secs must be positive".to_string(),
+            ));
         }
+        validate_rpc_providers(&self.default_providers)
+    }
+
+    /// The time it takes to produce the given number of blocks.
+    pub fn blocks_duration(&self, blocks: u32) -> Duration {
+        Duration::from_secs(self.block_time_secs) * blocks
+    }
+
+    /// Symbol of the token twinning the native currency, e.g. `icPOL`.
+    pub fn twin_token_symbol(&self) -> String {
+        format!("ic{}", self.native_symbol)
+    }
+}
+
+impl Display for EvmNetwork {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (chain id {})", self.name, self.chain_id)
     }
 }
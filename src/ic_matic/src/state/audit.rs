@@ -0,0 +1,232 @@
+use super::{
+    event::{Event, EventType},
+    transactions::{ReimbursementIndex, ReimbursementRequest},
+    State,
+};
+use crate::erc20::Erc20Token;
+use crate::storage::{record_event, with_event_iter};
+
+/// Updates the state to reflect the given state transition.
+pub fn apply_state_transition(state: &mut State, payload: &EventType) {
+    match payload {
+        EventType::Init(init_arg) => {
+            panic!("state re-initialization is not allowed: {init_arg:?}");
+        }
+        EventType::Upgrade(upgrade_arg) => state
+            .upgrade(upgrade_arg.clone())
+            .expect("applying upgrade event should succeed"),
+        EventType::AcceptedDeposit(deposit_event) => {
+            state.record_event_to_mint(deposit_event);
+        }
+        EventType::InvalidDeposit {
+            event_source,
+            reason,
+        } => {
+            let _ = state.record_invalid_deposit(*event_source, reason.clone());
+        }
+        EventType::MintedIcMatic {
+            event_source,
+            mint_block_index,
+        } => {
+            state.record_successful_mint(*event_source, *mint_block_index);
+        }
+        EventType::SyncedToBlock { block_number } => {
+            state.last_scraped_block_number = *block_number;
+        }
+        EventType::SkippedBlock(block_number) => {
+            state.record_skipped_block(*block_number);
+        }
+        EventType::QuarantinedDeposit { event_source } => {
+            let _ = state.record_quarantined_deposit(*event_source);
+        }
+        EventType::UnsupportedDeposit { event_source } => {
+            state.record_unsupported_deposit(*event_source);
+        }
+        EventType::AcceptedMaticWithdrawalRequest(request) => {
+            state
+                .polygon_transactions
+                .record_withdrawal_request(request.clone());
+        }
+        EventType::CreatedTransaction {
+            withdrawal_id,
+            transaction,
+        } => {
+            state
+                .polygon_transactions
+                .record_created_transaction(*withdrawal_id, transaction.clone());
+        }
+        EventType::SignedTransaction {
+            withdrawal_id,
+            transaction,
+        } => {
+            state
+                .polygon_transactions
+                .record_signed_transaction(*withdrawal_id, transaction.clone());
+        }
+        EventType::ReplacedTransaction {
+            withdrawal_id,
+            transaction,
+        } => {
+            state
+                .polygon_transactions
+                .record_replaced_transaction(*withdrawal_id, transaction.clone());
+        }
+        EventType::SyncedTransactionNonce { next_nonce } => {
+            state.polygon_transactions.record_next_nonce(*next_nonce);
+        }
+        EventType::FinalizedTransaction {
+            withdrawal_id,
+            transaction_receipt,
+        } => {
+            state.record_finalized_transaction(*withdrawal_id, transaction_receipt.clone());
+        }
+        EventType::AbandonedWithdrawalRequest {
+            withdrawal_id,
+            reason,
+        } => {
+            state
+                .polygon_transactions
+                .record_abandoned_withdrawal_request(*withdrawal_id, reason.clone());
+        }
+        EventType::ReimbursedWithdrawal {
+            withdrawal_id,
+            reimbursed_in_block,
+        } => {
+            state.polygon_transactions.record_reimbursed(
+                ReimbursementIndex::IcMatic {
+                    withdrawal_id: *withdrawal_id,
+                },
+                *reimbursed_in_block,
+            );
+        }
+        EventType::QuarantinedReimbursement { withdrawal_id } => {
+            state
+                .polygon_transactions
+                .record_quarantined_reimbursement(ReimbursementIndex::IcMatic {
+                    withdrawal_id: *withdrawal_id,
+                });
+        }
+        EventType::SyncedErc20ToBlock { block_number } => {
+            state.last_erc20_scraped_block_number = *block_number;
+        }
+        EventType::MintedIcErc20 {
+            event_source,
+            mint_block_index,
+        } => {
+            state.record_successful_mint(*event_source, *mint_block_index);
+        }
+        EventType::AcceptedErc20WithdrawalRequest(request) => {
+            state
+                .polygon_transactions
+                .record_withdrawal_request(request.clone());
+        }
+        EventType::FailedErc20WithdrawalRequest {
+            withdrawal_id,
+            reimbursed_amount,
+            to,
+        } => {
+            state
+                .polygon_transactions
+                .record_failed_erc20_withdrawal_request(ReimbursementRequest {
+                    withdrawal_id: *withdrawal_id,
+                    reimbursed_amount: *reimbursed_amount,
+                    to: *to,
+                    transaction_hash: None,
+                });
+        }
+        EventType::ReimbursedErc20Withdrawal {
+            withdrawal_id,
+            ledger_id,
+            reimbursed_in_block,
+        } => {
+            state.polygon_transactions.record_reimbursed(
+                ReimbursementIndex::IcErc20 {
+                    withdrawal_id: *withdrawal_id,
+                    ledger_id: *ledger_id,
+                },
+                *reimbursed_in_block,
+            );
+        }
+        EventType::QuarantinedErc20Reimbursement {
+            withdrawal_id,
+            ledger_id,
+        } => {
+            state
+                .polygon_transactions
+                .record_quarantined_reimbursement(ReimbursementIndex::IcErc20 {
+                    withdrawal_id: *withdrawal_id,
+                    ledger_id: *ledger_id,
+                });
+        }
+        EventType::AddedErc20Token(token) => {
+            let token = Erc20Token::try_from(token.clone())
+                .expect("BUG: added ERC-20 token should be valid");
+            state.record_added_erc20_token(token);
+        }
+        EventType::RemovedErc20Token {
+            erc20_contract_address,
+        } => {
+            state.record_removed_erc20_token(erc20_contract_address);
+        }
+        EventType::UpdatedRpcProviders { providers } => {
+            state.rpc_providers = providers.clone();
+        }
+        EventType::ScrapedSkippedBlock { block_number }
+        | EventType::AbandonedSkippedBlock { block_number } => {
+            state.remove_skipped_block(*block_number);
+        }
+        EventType::FailedSkippedBlockRetry {
+            block_number,
+            next_retry_at,
+        } => {
+            state.record_failed_skipped_block_retry(*block_number, *next_retry_at);
+        }
+        EventType::ForcedSkippedBlockRetry { block_number } => {
+            state.record_forced_skipped_block_retry(*block_number);
+        }
+        EventType::DetectedReorg {
+            event_source,
+            block_hash,
+        } => {
+            state.record_reorged_deposit(*event_source, *block_hash);
+        }
+        EventType::SentTransaction {
+            withdrawal_id,
+            transaction_hash,
+        } => {
+            state
+                .polygon_transactions
+                .record_sent_transaction(*withdrawal_id, *transaction_hash);
+        }
+    }
+}
+
+/// Records the given event payload in the event log and updates the state to reflect the change.
+pub fn process_event(state: &mut State, payload: EventType) {
+    apply_state_transition(state, &payload);
+    record_event(payload);
+}
+
+/// Recomputes the minter state from the event log.
+///
+/// # Panics
+///
+/// This function panics if:
+///   * The event log is empty.
+///   * The first event in the log is not an Init event.
+///   * One of the events in the log invalidates the minter's state invariants.
+pub fn replay_events() -> State {
+    with_event_iter(|mut events| {
+        let mut state = match events.next().expect("the event log should not be empty") {
+            Event {
+                payload: EventType::Init(init_arg),
+                ..
+            } => State::try_from(init_arg).expect("state initialization should succeed"),
+            other => panic!("the first event must be an Init event, got: {other:?}"),
+        };
+        for event in events {
+            apply_state_transition(&mut state, &event.payload);
+        }
+        state
+    })
+}
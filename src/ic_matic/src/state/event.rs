@@ -0,0 +1,293 @@
+use candid::Principal;
+use minicbor::{Decode, Encode};
+
+use crate::{
+    erc20::Erc20TokenArg,
+    events_utils::{EventSource, ReceivedPolygonEvent},
+    lifecycle::{init::InitArg, upgrade::UpgradeArg},
+    log_types::{address::Address, hash::Hash},
+    numeric::{BlockNumber, LedgerBurnIndex, LedgerMintIndex, TransactionNonce, Wei},
+    rpc_providers::RpcProvider,
+    state::transactions::{Erc20WithdrawalRequest, MaticWithdrawalRequest},
+    tx::{Eip1559TransactionRequest, SignedEip1559TransactionRequest, TransactionReceipt},
+};
+
+/// The event describing the icMATIC minter state transition.
+#[derive(Clone, Debug, Eq, PartialEq, Encode, Decode)]
+pub enum EventType {
+    /// The minter initialization event.
+    /// Must be the first event in the log.
+    #[n(0)]
+    Init(#[n(0)] InitArg),
+    /// The minter upgraded with the specified arguments.
+    #[n(1)]
+    Upgrade(#[n(0)] UpgradeArg),
+    /// The minter discovered a MATIC deposit in the helper contract logs.
+    #[n(2)]
+    AcceptedDeposit(#[n(0)] ReceivedPolygonEvent),
+    /// The minter discovered an invalid deposit in the helper contract logs.
+    #[n(3)]
+    InvalidDeposit {
+        /// The unique identifier of the deposit on the Polygon network.
+        #[n(0)]
+        event_source: EventSource,
+        /// The reason why the minter considers the deposit invalid.
+        #[n(1)]
+        reason: String,
+    },
+    /// The minter minted icMATIC in response to a deposit.
+    #[n(4)]
+    MintedIcMatic {
+        /// The unique identifier of the deposit on the Polygon network.
+        #[n(0)]
+        event_source: EventSource,
+        /// The transaction index on the icMATIC ledger.
+        #[cbor(n(1), with = "crate::cbor::id")]
+        mint_block_index: LedgerMintIndex,
+    },
+    /// The minter processed the helper smart contract logs up to the specified height.
+    #[n(5)]
+    SyncedToBlock {
+        /// The last processed block number (inclusive).
+        #[n(0)]
+        block_number: BlockNumber,
+    },
+    /// The minter could not scrape the logs of the specified block.
+    #[n(6)]
+    SkippedBlock(#[n(0)] BlockNumber),
+    /// The minter could not determine whether the deposit was minted
+    /// and will not process it further.
+    #[n(7)]
+    QuarantinedDeposit {
+        /// The unique identifier of the deposit on the Polygon network.
+        #[n(0)]
+        event_source: EventSource,
+    },
+    /// The minter burned icMATIC and accepted the corresponding MATIC withdrawal request.
+    #[n(8)]
+    AcceptedMaticWithdrawalRequest(#[n(0)] MaticWithdrawalRequest),
+    /// The minter created a Polygon transaction for a withdrawal request.
+    #[n(9)]
+    CreatedTransaction {
+        /// The icMATIC burn index identifying the withdrawal request.
+        #[cbor(n(0), with = "crate::cbor::id")]
+        withdrawal_id: LedgerBurnIndex,
+        /// The unsigned transaction.
+        #[n(1)]
+        transaction: Eip1559TransactionRequest,
+    },
+    /// The minter signed a Polygon transaction for a withdrawal request.
+    #[n(10)]
+    SignedTransaction {
+        /// The icMATIC burn index identifying the withdrawal request.
+        #[cbor(n(0), with = "crate::cbor::id")]
+        withdrawal_id: LedgerBurnIndex,
+        /// The signed transaction.
+        #[n(1)]
+        transaction: SignedEip1559TransactionRequest,
+    },
+    /// The minter created a transaction with bumped fees to replace
+    /// the stuck transaction of a withdrawal request.
+    #[n(11)]
+    ReplacedTransaction {
+        /// The icMATIC burn index identifying the withdrawal request.
+        #[cbor(n(0), with = "crate::cbor::id")]
+        withdrawal_id: LedgerBurnIndex,
+        /// The unsigned transaction, with the same nonce as the replaced one.
+        #[n(1)]
+        transaction: Eip1559TransactionRequest,
+    },
+    /// The minter's address sent more finalized transactions than the minter issued,
+    /// the next transaction nonce is moved forward accordingly.
+    #[n(12)]
+    SyncedTransactionNonce {
+        /// The nonce of the next created transaction.
+        #[n(0)]
+        next_nonce: TransactionNonce,
+    },
+    /// One of the transactions of a withdrawal request was mined in a finalized block.
+    #[n(13)]
+    FinalizedTransaction {
+        /// The icMATIC burn index identifying the withdrawal request.
+        #[cbor(n(0), with = "crate::cbor::id")]
+        withdrawal_id: LedgerBurnIndex,
+        /// The receipt of the mined transaction.
+        #[n(1)]
+        transaction_receipt: TransactionReceipt,
+    },
+    /// The minter gave up on a withdrawal request for which no transaction could be sent,
+    /// the burned icMATIC are to be reimbursed.
+    #[n(14)]
+    AbandonedWithdrawalRequest {
+        /// The icMATIC burn index identifying the withdrawal request.
+        #[cbor(n(0), with = "crate::cbor::id")]
+        withdrawal_id: LedgerBurnIndex,
+        /// Why no transaction could be sent.
+        #[n(1)]
+        reason: String,
+    },
+    /// The minter minted back the icMATIC of a failed withdrawal.
+    #[n(15)]
+    ReimbursedWithdrawal {
+        /// The icMATIC burn index identifying the withdrawal request.
+        #[cbor(n(0), with = "crate::cbor::id")]
+        withdrawal_id: LedgerBurnIndex,
+        /// The transaction index on the icMATIC ledger.
+        #[cbor(n(1), with = "crate::cbor::id")]
+        reimbursed_in_block: LedgerMintIndex,
+    },
+    /// The minter could not determine whether the reimbursement was minted
+    /// and will not process it further.
+    #[n(16)]
+    QuarantinedReimbursement {
+        /// The icMATIC burn index identifying the withdrawal request.
+        #[cbor(n(0), with = "crate::cbor::id")]
+        withdrawal_id: LedgerBurnIndex,
+    },
+    /// The minter processed the ERC-20 deposits in the helper contract logs
+    /// up to the specified height.
+    #[n(17)]
+    SyncedErc20ToBlock {
+        /// The last processed block number (inclusive).
+        #[n(0)]
+        block_number: BlockNumber,
+    },
+    /// The minter minted icERC20 in response to a deposit.
+    #[n(18)]
+    MintedIcErc20 {
+        /// The unique identifier of the deposit on the Polygon network.
+        #[n(0)]
+        event_source: EventSource,
+        /// The transaction index on the icERC20 ledger.
+        #[cbor(n(1), with = "crate::cbor::id")]
+        mint_block_index: LedgerMintIndex,
+    },
+    /// The minter burned icMATIC and icERC20 and accepted the corresponding
+    /// ERC-20 withdrawal request.
+    #[n(19)]
+    AcceptedErc20WithdrawalRequest(#[n(0)] Erc20WithdrawalRequest),
+    /// The minter burned the icMATIC paying for the fee of an ERC-20 withdrawal
+    /// but failed to burn the icERC20, the icMATIC are to be reimbursed.
+    #[n(20)]
+    FailedErc20WithdrawalRequest {
+        /// The icMATIC burn index identifying the withdrawal request.
+        #[cbor(n(0), with = "crate::cbor::id")]
+        withdrawal_id: LedgerBurnIndex,
+        /// The burned icMATIC.
+        #[n(1)]
+        reimbursed_amount: Wei,
+        /// The owner of the burned icMATIC.
+        #[cbor(n(2), with = "crate::cbor::principal")]
+        to: Principal,
+    },
+    /// The minter minted back the icERC20 of a failed ERC-20 withdrawal.
+    #[n(21)]
+    ReimbursedErc20Withdrawal {
+        /// The icMATIC burn index identifying the withdrawal request.
+        #[cbor(n(0), with = "crate::cbor::id")]
+        withdrawal_id: LedgerBurnIndex,
+        /// The icERC20 ledger.
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        ledger_id: Principal,
+        /// The transaction index on the icERC20 ledger.
+        #[cbor(n(2), with = "crate::cbor::id")]
+        reimbursed_in_block: LedgerMintIndex,
+    },
+    /// The minter could not determine whether the icERC20 reimbursement was minted
+    /// and will not process it further.
+    #[n(22)]
+    QuarantinedErc20Reimbursement {
+        /// The icMATIC burn index identifying the withdrawal request.
+        #[cbor(n(0), with = "crate::cbor::id")]
+        withdrawal_id: LedgerBurnIndex,
+        /// The icERC20 ledger.
+        #[cbor(n(1), with = "crate::cbor::principal")]
+        ledger_id: Principal,
+    },
+    /// A controller added a supported ERC-20 token.
+    #[n(23)]
+    AddedErc20Token(#[n(0)] Erc20TokenArg),
+    /// A controller removed a supported ERC-20 token.
+    #[n(24)]
+    RemovedErc20Token {
+        /// The address of the token contract.
+        #[n(0)]
+        erc20_contract_address: Address,
+    },
+    /// A controller replaced the JSON-RPC providers of the Polygon network.
+    #[n(25)]
+    UpdatedRpcProviders {
+        /// The providers replacing the previous ones.
+        #[n(0)]
+        providers: Vec<RpcProvider>,
+    },
+    /// The minter scraped the logs of a previously skipped block.
+    #[n(26)]
+    ScrapedSkippedBlock {
+        /// The number of the skipped block.
+        #[n(0)]
+        block_number: BlockNumber,
+    },
+    /// A controller gave up on scraping the logs of a skipped block.
+    #[n(27)]
+    AbandonedSkippedBlock {
+        /// The number of the skipped block.
+        #[n(0)]
+        block_number: BlockNumber,
+    },
+    /// The minter saw an accepted deposit in another block than the one it was accepted in,
+    /// or removed from the chain by a reorganization.
+    #[n(28)]
+    DetectedReorg {
+        /// The unique identifier of the deposit on the Polygon network.
+        #[n(0)]
+        event_source: EventSource,
+        /// The hash of the block the deposit was seen in or removed from.
+        #[n(1)]
+        block_hash: Hash,
+    },
+    /// The JSON-RPC providers accepted, or already had, the signed transaction of a withdrawal.
+    #[n(29)]
+    SentTransaction {
+        /// The icMATIC burn index identifying the withdrawal request.
+        #[cbor(n(0), with = "crate::cbor::id")]
+        withdrawal_id: LedgerBurnIndex,
+        /// The hash of the sent transaction.
+        #[n(1)]
+        transaction_hash: Hash,
+    },
+    /// The minter cannot mint an accepted deposit because its token is not supported anymore.
+    #[n(30)]
+    UnsupportedDeposit {
+        /// The unique identifier of the deposit on the Polygon network.
+        #[n(0)]
+        event_source: EventSource,
+    },
+    /// The minter failed to scrape the logs of a skipped block again.
+    #[n(31)]
+    FailedSkippedBlockRetry {
+        /// The number of the skipped block.
+        #[n(0)]
+        block_number: BlockNumber,
+        /// The canister time after which the block is scraped again.
+        #[n(1)]
+        next_retry_at: u64,
+    },
+    /// A controller asked to scrape the logs of a skipped block again without delay.
+    #[n(32)]
+    ForcedSkippedBlockRetry {
+        /// The number of the skipped block.
+        #[n(0)]
+        block_number: BlockNumber,
+    },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Encode, Decode)]
+pub struct Event {
+    /// The canister time at which the minter generated this event.
+    #[n(0)]
+    pub timestamp: u64,
+    /// The event type.
+    #[n(1)]
+    pub payload: EventType,
+}
@@ -0,0 +1,958 @@
+use candid::{Nat, Principal};
+use ic_cdk::api::management_canister::ecdsa::EcdsaPublicKeyResponse;
+use ic_crypto_ecdsa_secp256k1::PublicKey;
+use std::{
+    cell::RefCell,
+    cmp::{max, min},
+    collections::{btree_map, BTreeMap, BTreeSet, HashSet},
+    fmt::{Display, Formatter},
+    ops::RangeInclusive,
+};
+
+use crate::{
+    endpoints::{
+        CandidBlockTag, DepositStatus, Reimbursement, TxFinalizedStatus, WithdrawalDetail,
+        WithdrawalStatus,
+    },
+    erc20::{Erc20Token, Erc20TokenArg},
+    events_utils::{EventSource, ReceivedPolygonEvent},
+    lifecycle::upgrade::UpgradeArg,
+    log_types::{
+        address::{ecdsa_public_key_to_address, Address},
+        hash::Hash,
+    },
+    management::{ecdsa_public_key, MAIN_DERIVATION_PATH},
+    numeric::{BlockNumber, LedgerBurnIndex, LedgerMintIndex, LogIndex, Wei},
+    rpc_client::{ConsensusStrategy, RequestCost},
+    rpc_providers::{validate_rpc_providers, EvmNetwork, RpcProvider},
+    tx::{GasFeeEstimate, TransactionReceipt, TransactionStatus},
+};
+use transactions::{PolygonTransactions, ReimbursementIndex, WithdrawalRequest, WithdrawalStage};
+
+pub mod audit;
+pub mod event;
+pub mod transactions;
+
+thread_local! {
+    pub static STATE: RefCell<Option<State>> = RefCell::default();
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MintedEvent {
+    pub deposit_event: ReceivedPolygonEvent,
+    pub mint_block_index: LedgerMintIndex,
+    /// The symbol of the minted token, as supported when the deposit was minted.
+    pub token_symbol: String,
+}
+
+impl MintedEvent {
+    pub fn source(&self) -> EventSource {
+        self.deposit_event.source()
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum InvalidStateError {
+    InvalidTransactionNonce(String),
+    InvalidEcdsaKeyName(String),
+    InvalidLedgerId(String),
+    InvalidEthereumContractAddress(String),
+    InvalidErc20HelperContractAddress(String),
+    InvalidMinimumWithdrawalAmount(String),
+    InvalidLastScrapedBlockNumber(String),
+    InvalidLastErc20ScrapedBlockNumber(String),
+    InvalidErc20Token(String),
+    InvalidConsensusStrategy(String),
+    InvalidRpcProviders(String),
+    InvalidEvmNetwork(String),
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum InvalidEventReason {
+    /// Deposit is invalid and was never minted.
+    /// This is most likely due to a user error (e.g., user's IC principal cannot be decoded)
+    /// or there is a critical issue in the logs returned from the JSON-RPC providers.
+    InvalidDeposit(String),
+
+    /// Deposit is valid but it's unknown whether it was minted or not,
+    /// most likely because there was an unexpected panic in the callback.
+    /// The deposit is quarantined to avoid any double minting and
+    /// will not be further processed without manual intervention.
+    QuarantinedDeposit,
+}
+
+impl Display for InvalidEventReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvalidEventReason::InvalidDeposit(reason) => {
+                write!(f, "Invalid deposit: {}", reason)
+            }
+            InvalidEventReason::QuarantinedDeposit => {
+                write!(f, "Quarantined deposit")
+            }
+        }
+    }
+}
+
+/// The sources of all the deposits of the given transaction, in order.
+fn transaction_sources(transaction_hash: Hash) -> RangeInclusive<EventSource> {
+    EventSource {
+        transaction_hash,
+        log_index: LogIndex::ZERO,
+    }..=EventSource {
+        transaction_hash,
+        log_index: LogIndex::MAX,
+    }
+}
+
+fn validate_ecdsa_key_name(key_name: &str) -> Result<(), InvalidStateError> {
+    if key_name.trim().is_empty() {
+        return Err(InvalidStateError::InvalidEcdsaKeyName(
+            "ecdsa_key_name cannot be blank".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_ledger_id(ledger_id: &Principal) -> Result<(), InvalidStateError> {
+    if ledger_id == &Principal::anonymous() {
+        return Err(InvalidStateError::InvalidLedgerId(
+            "ledger_id cannot be the anonymous principal".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_helper_contract_address(address: &Address) -> Result<(), InvalidStateError> {
+    if address == &Address::ZERO {
+        return Err(InvalidStateError::InvalidEthereumContractAddress(
+            "eth_helper_contract_address cannot be the zero address".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_minimum_withdrawal_amount(amount: &Wei) -> Result<(), InvalidStateError> {
+    if amount == &Wei::ZERO {
+        return Err(InvalidStateError::InvalidMinimumWithdrawalAmount(
+            "minimum_withdrawal_amount must be positive".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that the strategy can be satisfied by `provider_count` providers.
+pub fn validate_consensus_strategy(
+    strategy: &ConsensusStrategy,
+    provider_count: usize,
+) -> Result<(), InvalidStateError> {
+    if let ConsensusStrategy::Threshold { min } = strategy {
+        if *min == 0 {
+            return Err(InvalidStateError::InvalidConsensusStrategy(
+                "the threshold must be positive".to_string(),
+            ));
+        }
+        if *min as usize > provider_count {
+            return Err(InvalidStateError::InvalidConsensusStrategy(format!(
+                "the threshold {min} exceeds the number of providers {provider_count}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The failed attempts to scrape a skipped block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SkippedBlockRetry {
+    pub attempts: u32,
+    /// The canister time after which the block is scraped again.
+    pub next_retry_at: u64,
+}
+
+#[derive(Debug, Hash, Copy, Clone, PartialEq, Eq)]
+pub enum TaskType {
+    Mint,
+    RetrieveEth,
+    ScrapEthLogs,
+    RefreshGasFeeEstimate,
+    Reimbursement,
+    MintCkErc20,
+    RetrySkippedBlocks,
+}
+
+pub struct State {
+    /// The EVM chain whose deposits and withdrawals the minter processes.
+    pub evm_network: EvmNetwork,
+    pub ecdsa_key_name: String,
+    pub icmatic_ledger_id: Principal,
+    pub eth_helper_contract_address: Option<Address>,
+    pub ecdsa_public_key: Option<EcdsaPublicKeyResponse>,
+    pub icmatic_minimum_withdrawal_amount: Wei,
+    pub ethereum_block_height: CandidBlockTag,
+    /// Number of blocks below the latest one that are not scraped yet when
+    /// `ethereum_block_height` is `Latest`, as they may still be reorganized.
+    pub latest_block_confirmations: u64,
+    /// The first block scraped since the cursors were last set by an init or upgrade argument.
+    pub first_scraped_block_number: BlockNumber,
+    pub last_scraped_block_number: BlockNumber,
+    pub last_erc20_scraped_block_number: BlockNumber,
+    pub last_observed_block_number: Option<BlockNumber>,
+    pub events_to_mint: BTreeMap<EventSource, ReceivedPolygonEvent>,
+    pub minted_events: BTreeMap<EventSource, MintedEvent>,
+    pub invalid_events: BTreeMap<EventSource, InvalidEventReason>,
+    /// Accepted deposits later seen in another block or removed from the chain, with the
+    /// hash of that block.
+    pub reorged_deposits: BTreeMap<EventSource, Hash>,
+    /// Supported ERC-20 tokens, by contract address.
+    pub erc20_tokens: BTreeMap<Address, Erc20Token>,
+    /// How differing results of the JSON-RPC providers are reduced to a single one.
+    pub consensus_strategy: ConsensusStrategy,
+    /// JSON-RPC providers of the Polygon network queried through the EVM RPC canister.
+    pub rpc_providers: Vec<RpcProvider>,
+    pub polygon_transactions: PolygonTransactions,
+    pub skipped_blocks: BTreeSet<BlockNumber>,
+    /// Failed attempts to scrape the skipped blocks.
+    pub skipped_block_retries: BTreeMap<BlockNumber, SkippedBlockRetry>,
+    /// Current balance of matic held by the minter.
+    /// Computed based on audit events.
+    pub matic_balance: ethnum::u256,
+    /// Current balance of each ERC-20 token held by the minter.
+    /// Computed based on audit events.
+    pub erc20_balances: BTreeMap<Address, ethnum::u256>,
+
+    /// Per-principal lock for pending withdrawals
+    pub pending_withdrawal_principals: BTreeSet<Principal>,
+
+    /// Locks preventing concurrent execution timer tasks
+    pub active_tasks: HashSet<TaskType>,
+
+    /// Number of HTTP outcalls since the last upgrade.
+    /// Used to correlate request and response in logs.
+    pub http_request_counter: u64,
+
+    /// The last gas fee estimate, with the canister time at which it was computed.
+    pub last_transaction_price_estimate: Option<(u64, GasFeeEstimate)>,
+
+    /// Number of results of each provider that differed from the agreed one
+    /// since the last upgrade.
+    pub provider_disagreements: BTreeMap<String, u64>,
+
+    /// Cycles charged by the EVM RPC canister for each JSON-RPC method
+    /// since the last upgrade.
+    pub rpc_cycles_spent: BTreeMap<String, u128>,
+
+    /// Cost of the requests to each provider, by JSON-RPC method, provider URL
+    /// and response size estimate.
+    pub rpc_request_costs: BTreeMap<(String, String, u64), RequestCost>,
+
+    /// Number of calls of each JSON-RPC method whose cost could not be estimated
+    /// since the last upgrade, which were attached a fixed amount of cycles instead.
+    pub rpc_cost_estimate_fallbacks: BTreeMap<String, u64>,
+}
+
+impl State {
+    /// The minter's address on Polygon, known once the threshold ECDSA public key is cached.
+    pub fn minter_address(&self) -> Option<Address> {
+        let public_key = PublicKey::deserialize_sec1(&self.ecdsa_public_key.as_ref()?.public_key)
+            .expect("BUG: the cached public key is invalid");
+        Some(ecdsa_public_key_to_address(&public_key))
+    }
+
+    pub fn validate_config(&self) -> Result<(), InvalidStateError> {
+        validate_ecdsa_key_name(&self.ecdsa_key_name)?;
+        validate_ledger_id(&self.icmatic_ledger_id)?;
+        if let Some(address) = &self.eth_helper_contract_address {
+            validate_helper_contract_address(address)?;
+        }
+        validate_minimum_withdrawal_amount(&self.icmatic_minimum_withdrawal_amount)?;
+        self.evm_network.validate()?;
+        validate_rpc_providers(&self.rpc_providers)?;
+        validate_consensus_strategy(&self.consensus_strategy, self.rpc_providers.len())?;
+        let mut ledger_ids = BTreeSet::from([self.icmatic_ledger_id]);
+        for token in self.erc20_tokens.values() {
+            if !ledger_ids.insert(token.ledger_id) {
+                return Err(InvalidStateError::InvalidErc20Token(format!(
+                    "ledger {} of {} is already used by another token",
+                    token.ledger_id, token.symbol
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the supported ERC-20 token with the given contract address.
+    pub fn erc20_token(&self, erc20_contract_address: &Address) -> Option<&Erc20Token> {
+        self.erc20_tokens.get(erc20_contract_address)
+    }
+
+    /// Returns the symbol of the token twinning the native currency of the network.
+    pub fn icmatic_token_symbol(&self) -> String {
+        self.evm_network.twin_token_symbol()
+    }
+
+    /// Returns the ledger and symbol of the twin token of the given Polygon token,
+    /// `Address::ZERO` being MATIC.
+    pub fn ledger_of(&self, token_contract_address: &Address) -> Option<(Principal, String)> {
+        if token_contract_address == &Address::ZERO {
+            return Some((self.icmatic_ledger_id, self.icmatic_token_symbol()));
+        }
+        self.erc20_token(token_contract_address)
+            .map(|token| (token.ledger_id, token.symbol.clone()))
+    }
+
+    /// Checks that the given token can be added to the supported ones:
+    /// neither its contract nor its ledger may already be used.
+    pub fn validate_erc20_token_addition(
+        &self,
+        token: &Erc20Token,
+    ) -> Result<(), InvalidStateError> {
+        if self
+            .erc20_tokens
+            .contains_key(&token.erc20_contract_address)
+        {
+            return Err(InvalidStateError::InvalidErc20Token(format!(
+                "token {} is already supported",
+                token.erc20_contract_address
+            )));
+        }
+        if token.ledger_id == self.icmatic_ledger_id
+            || self
+                .erc20_tokens
+                .values()
+                .any(|known| known.ledger_id == token.ledger_id)
+        {
+            return Err(InvalidStateError::InvalidErc20Token(format!(
+                "ledger {} of {} is already used by another token",
+                token.ledger_id, token.symbol
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that the given token can be removed from the supported ones:
+    /// none of its deposits may wait to be minted, and none of its withdrawals
+    /// or reimbursements may be in progress.
+    pub fn validate_erc20_token_removal(
+        &self,
+        erc20_contract_address: &Address,
+    ) -> Result<(), InvalidStateError> {
+        let token = self.erc20_token(erc20_contract_address).ok_or_else(|| {
+            InvalidStateError::InvalidErc20Token(format!(
+                "token {erc20_contract_address} is not supported"
+            ))
+        })?;
+        if self
+            .events_to_mint
+            .values()
+            .any(|event| &event.token_contract_address == erc20_contract_address)
+        {
+            return Err(InvalidStateError::InvalidErc20Token(format!(
+                "deposits of {} are waiting to be minted",
+                token.symbol
+            )));
+        }
+        if self
+            .polygon_transactions
+            .has_pending_erc20_operations(&token.ledger_id)
+        {
+            return Err(InvalidStateError::InvalidErc20Token(format!(
+                "withdrawals or reimbursements of {} are in progress",
+                token.symbol
+            )));
+        }
+        Ok(())
+    }
+
+    fn record_added_erc20_token(&mut self, token: Erc20Token) {
+        self.validate_erc20_token_addition(&token)
+            .unwrap_or_else(|e| panic!("BUG: cannot add token {token:?}: {e:?}"));
+        self.erc20_tokens
+            .insert(token.erc20_contract_address, token);
+    }
+
+    fn record_removed_erc20_token(&mut self, erc20_contract_address: &Address) {
+        self.validate_erc20_token_removal(erc20_contract_address)
+            .unwrap_or_else(|e| panic!("BUG: cannot remove token {erc20_contract_address}: {e:?}"));
+        self.erc20_tokens.remove(erc20_contract_address);
+    }
+
+    /// Applies the upgrade arguments. The whole argument is validated before the state is
+    /// modified, so that a rejected upgrade leaves the state unchanged.
+    fn upgrade(&mut self, upgrade_args: UpgradeArg) -> Result<(), InvalidStateError> {
+        use std::str::FromStr;
+
+        let UpgradeArg {
+            ecdsa_key_name,
+            icmatic_ledger_id,
+            helper_contract_address,
+            minimum_withdrawal_amount,
+            last_scraped_block_number,
+            ethereum_block_height,
+            erc20_tokens,
+            consensus_strategy,
+            latest_block_confirmations,
+        } = upgrade_args;
+        if let Some(key_name) = &ecdsa_key_name {
+            validate_ecdsa_key_name(key_name)?;
+        }
+        if let Some(ledger_id) = &icmatic_ledger_id {
+            validate_ledger_id(ledger_id)?;
+        }
+        let helper_contract_address = helper_contract_address
+            .map(|address| {
+                let address = Address::from_str(&address).map_err(|e| {
+                    InvalidStateError::InvalidEthereumContractAddress(format!("ERROR: {}", e))
+                })?;
+                validate_helper_contract_address(&address)?;
+                Ok(address)
+            })
+            .transpose()?;
+        let minimum_withdrawal_amount = minimum_withdrawal_amount
+            .map(|amount| {
+                let amount = Wei::try_from(amount).map_err(|e| {
+                    InvalidStateError::InvalidMinimumWithdrawalAmount(format!("ERROR: {}", e))
+                })?;
+                validate_minimum_withdrawal_amount(&amount)?;
+                Ok(amount)
+            })
+            .transpose()?;
+        let last_scraped_block_number = last_scraped_block_number
+            .map(|block_number| {
+                let block_number = BlockNumber::try_from(block_number).map_err(|e| {
+                    InvalidStateError::InvalidLastScrapedBlockNumber(format!("ERROR: {}", e))
+                })?;
+                let last_scraped = max(
+                    self.last_scraped_block_number,
+                    self.last_erc20_scraped_block_number,
+                );
+                if block_number < last_scraped {
+                    return Err(InvalidStateError::InvalidLastScrapedBlockNumber(format!(
+                        "ERROR: cannot rewind last_scraped_block_number from {} to {}",
+                        last_scraped, block_number
+                    )));
+                }
+                let first_scraped = block_number.checked_increment().ok_or_else(|| {
+                    InvalidStateError::InvalidLastScrapedBlockNumber(
+                        "ERROR: last_scraped_block_number is at maximum value".to_string(),
+                    )
+                })?;
+                Ok((block_number, first_scraped))
+            })
+            .transpose()?;
+        if let Some(strategy) = &consensus_strategy {
+            validate_consensus_strategy(strategy, self.rpc_providers.len())?;
+        }
+        let erc20_tokens = self.validate_upgraded_erc20_tokens(
+            erc20_tokens.unwrap_or_default(),
+            icmatic_ledger_id.unwrap_or(self.icmatic_ledger_id),
+        )?;
+
+        if let Some(key_name) = ecdsa_key_name {
+            self.ecdsa_key_name = key_name;
+            // The cached key belongs to the previous key name.
+            self.ecdsa_public_key = None;
+        }
+        if let Some(ledger_id) = icmatic_ledger_id {
+            self.icmatic_ledger_id = ledger_id;
+        }
+        if let Some(address) = helper_contract_address {
+            self.eth_helper_contract_address = Some(address);
+        }
+        if let Some(amount) = minimum_withdrawal_amount {
+            self.icmatic_minimum_withdrawal_amount = amount;
+        }
+        if let Some((block_number, first_scraped)) = last_scraped_block_number {
+            // The skipped blocks are never scraped: scraping restarts after the new cursor.
+            if block_number
+                > min(
+                    self.last_scraped_block_number,
+                    self.last_erc20_scraped_block_number,
+                )
+            {
+                self.first_scraped_block_number = first_scraped;
+            }
+            self.last_scraped_block_number = block_number;
+            self.last_erc20_scraped_block_number = block_number;
+        }
+        if let Some(block_height) = ethereum_block_height {
+            self.ethereum_block_height = block_height;
+        }
+        if let Some(strategy) = consensus_strategy {
+            self.consensus_strategy = strategy;
+        }
+        if let Some(confirmations) = latest_block_confirmations {
+            self.latest_block_confirmations = confirmations;
+        }
+        for token in erc20_tokens {
+            self.erc20_tokens
+                .insert(token.erc20_contract_address, token);
+        }
+        Ok(())
+    }
+
+    /// Returns the tokens of an upgrade that are not supported yet. A supported token may
+    /// be given again with the same data, any other token is checked as if it were added
+    /// with `add_erc20_token`, with `icmatic_ledger_id` as the ledger of icMATIC.
+    fn validate_upgraded_erc20_tokens(
+        &self,
+        args: Vec<Erc20TokenArg>,
+        icmatic_ledger_id: Principal,
+    ) -> Result<Vec<Erc20Token>, InvalidStateError> {
+        let mut added: Vec<Erc20Token> = Vec::new();
+        for arg in args {
+            let token = Erc20Token::try_from(arg)?;
+            match self.erc20_token(&token.erc20_contract_address) {
+                Some(known) if known == &token => continue,
+                Some(known) => {
+                    return Err(InvalidStateError::InvalidErc20Token(format!(
+                        "token {} is already supported as {known:?}",
+                        token.erc20_contract_address
+                    )))
+                }
+                None => self.validate_erc20_token_addition(&token)?,
+            }
+            if added
+                .iter()
+                .any(|other| other.erc20_contract_address == token.erc20_contract_address)
+            {
+                return Err(InvalidStateError::InvalidErc20Token(format!(
+                    "token {} is given twice",
+                    token.erc20_contract_address
+                )));
+            }
+            if added.iter().any(|other| other.ledger_id == token.ledger_id) {
+                return Err(InvalidStateError::InvalidErc20Token(format!(
+                    "ledger {} of {} is already used by another token",
+                    token.ledger_id, token.symbol
+                )));
+            }
+            added.push(token);
+        }
+        if let Some(token) = self
+            .erc20_tokens
+            .values()
+            .chain(added.iter())
+            .find(|token| token.ledger_id == icmatic_ledger_id)
+        {
+            return Err(InvalidStateError::InvalidErc20Token(format!(
+                "ledger {} of {} is already used by another token",
+                token.ledger_id, token.symbol
+            )));
+        }
+        Ok(added)
+    }
+
+    fn record_event_to_mint(&mut self, event: &ReceivedPolygonEvent) {
+        let event_source = event.source();
+        assert!(
+            !self.events_to_mint.contains_key(&event_source),
+            "there must be no two different events with the same source"
+        );
+        assert!(!self.minted_events.contains_key(&event_source));
+        assert!(!self.invalid_events.contains_key(&event_source));
+
+        self.events_to_mint.insert(event_source, *event);
+    }
+
+    pub fn is_processed(&self, event_source: &EventSource) -> bool {
+        self.events_to_mint.contains_key(event_source)
+            || self.minted_events.contains_key(event_source)
+            || self.invalid_events.contains_key(event_source)
+    }
+
+    /// Returns the accepted deposit, minted or not, with the given source.
+    pub fn accepted_deposit(&self, event_source: &EventSource) -> Option<&ReceivedPolygonEvent> {
+        self.events_to_mint.get(event_source).or_else(|| {
+            self.minted_events
+                .get(event_source)
+                .map(|event| &event.deposit_event)
+        })
+    }
+
+    /// Returns an accepted deposit of the given transaction that was included in a block
+    /// other than `block_hash`, meaning that the chain was reorganized since it was accepted.
+    pub fn reorged_deposit(
+        &self,
+        transaction_hash: Hash,
+        block_hash: Hash,
+    ) -> Option<&ReceivedPolygonEvent> {
+        let transaction = transaction_sources(transaction_hash);
+        self.events_to_mint
+            .range(transaction.clone())
+            .map(|(_source, event)| event)
+            .chain(
+                self.minted_events
+                    .range(transaction)
+                    .map(|(_source, event)| &event.deposit_event),
+            )
+            .find(|accepted| accepted.block_hash != block_hash)
+    }
+
+    /// Describes the withdrawal with the given id and the stage it reached.
+    pub fn withdrawal_detail(&self, withdrawal_id: &LedgerBurnIndex) -> Option<WithdrawalDetail> {
+        let (request, stage) = self.polygon_transactions.withdrawal_stage(withdrawal_id)?;
+        let (recipient_address, erc20_contract_address, withdrawal_amount, transaction_fee) =
+            match request {
+                WithdrawalRequest::Matic(request) => {
+                    let transaction = match stage {
+                        WithdrawalStage::Pending | WithdrawalStage::Abandoned(_) => None,
+                        WithdrawalStage::Created(tx) => Some(tx),
+                        WithdrawalStage::Signed(tx) => Some(&tx.transaction),
+                        WithdrawalStage::Finalized(finalized) => {
+                            Some(&finalized.transaction.transaction)
+                        }
+                    };
+                    (
+                        request.destination,
+                        None,
+                        Nat::from(request.withdrawal_amount),
+                        transaction.map(|tx| Nat::from(tx.max_transaction_fee())),
+                    )
+                }
+                WithdrawalRequest::Erc20(request) => (
+                    request.destination,
+                    Some(request.erc20_contract_address.to_string()),
+                    Nat::from(request.withdrawal_amount),
+                    Some(Nat::from(request.max_transaction_fee)),
+                ),
+            };
+        let mut effective_transaction_fee = None;
+        let status = match stage {
+            WithdrawalStage::Pending => WithdrawalStatus::Pending,
+            WithdrawalStage::Created(_) => WithdrawalStatus::TxCreated,
+            WithdrawalStage::Signed(tx) => {
+                let transaction_hash = tx.hash();
+                if self
+                    .polygon_transactions
+                    .sent_transaction_hash(withdrawal_id)
+                    == Some(&transaction_hash)
+                {
+                    WithdrawalStatus::TxSent {
+                        transaction_hash: transaction_hash.to_string(),
+                    }
+                } else {
+                    WithdrawalStatus::TxSigned {
+                        transaction_hash: transaction_hash.to_string(),
+                    }
+                }
+            }
+            WithdrawalStage::Finalized(finalized) => {
+                effective_transaction_fee =
+                    Some(Nat::from(finalized.receipt.effective_transaction_fee()));
+                let transaction_hash = finalized.receipt.transaction_hash.to_string();
+                match finalized.status() {
+                    TransactionStatus::Success => WithdrawalStatus::TxFinalized {
+                        transaction_hash,
+                        status: TxFinalizedStatus::Success,
+                    },
+                    TransactionStatus::Failure => self
+                        .withdrawal_reimbursed(withdrawal_id)
+                        .unwrap_or(WithdrawalStatus::TxFinalized {
+                            transaction_hash,
+                            status: TxFinalizedStatus::Failure,
+                        }),
+                }
+            }
+            WithdrawalStage::Abandoned(abandoned) => self
+                .withdrawal_reimbursed(withdrawal_id)
+                .unwrap_or_else(|| WithdrawalStatus::Abandoned {
+                    reason: abandoned.reason.clone(),
+                }),
+        };
+        Some(WithdrawalDetail {
+            withdrawal_id: Nat::from(withdrawal_id.get()),
+            from: request.from(),
+            recipient_address: recipient_address.to_string(),
+            erc20_contract_address,
+            withdrawal_amount,
+            transaction_fee,
+            effective_transaction_fee,
+            created_at: request.created_at(),
+            status,
+        })
+    }
+
+    fn withdrawal_reimbursed(&self, withdrawal_id: &LedgerBurnIndex) -> Option<WithdrawalStatus> {
+        let reimbursed = self
+            .polygon_transactions
+            .completed_reimbursements(withdrawal_id)?;
+        let transaction_hash = reimbursed
+            .iter()
+            .find_map(|(_index, reimbursed)| reimbursed.transaction_hash)
+            .map(|hash| hash.to_string());
+        let reimbursements = reimbursed
+            .into_iter()
+            .map(|(index, reimbursed)| Reimbursement {
+                ledger_id: match index {
+                    ReimbursementIndex::IcMatic { .. } => self.icmatic_ledger_id,
+                    ReimbursementIndex::IcErc20 { ledger_id, .. } => ledger_id,
+                },
+                reimbursed_amount: Nat::from(reimbursed.reimbursed_amount),
+                reimbursed_in_block: Nat::from(reimbursed.reimbursed_in_block.get()),
+            })
+            .collect();
+        Some(WithdrawalStatus::Reimbursed {
+            transaction_hash,
+            reimbursements,
+        })
+    }
+
+    /// Returns the status of the processed deposits of the given transaction, by log index.
+    pub fn deposit_statuses(&self, transaction_hash: Hash) -> BTreeMap<LogIndex, DepositStatus> {
+        let transaction = transaction_sources(transaction_hash);
+        let pending = self
+            .events_to_mint
+            .range(transaction.clone())
+            .map(|(source, _event)| (source.log_index, DepositStatus::Pending));
+        let minted = self
+            .minted_events
+            .range(transaction.clone())
+            .map(|(source, event)| {
+                (
+                    source.log_index,
+                    DepositStatus::Minted {
+                        mint_block_index: Nat::from(event.mint_block_index.get()),
+                        token_symbol: event.token_symbol.clone(),
+                    },
+                )
+            });
+        let invalid = self
+            .invalid_events
+            .range(transaction)
+            .map(|(source, reason)| {
+                let status = match reason {
+                    InvalidEventReason::InvalidDeposit(_) => {
+                        DepositStatus::Invalid(reason.to_string())
+                    }
+                    InvalidEventReason::QuarantinedDeposit => DepositStatus::Quarantined,
+                };
+                (source.log_index, status)
+            });
+        pending.chain(minted).chain(invalid).collect()
+    }
+
+    fn record_reorged_deposit(&mut self, event_source: EventSource, block_hash: Hash) {
+        assert!(
+            self.accepted_deposit(&event_source).is_some(),
+            "attempted to record a reorganization of an unknown deposit {event_source:?}"
+        );
+        self.reorged_deposits.insert(event_source, block_hash);
+    }
+
+    /// Records an invalid deposit.
+    /// Returns `true` if the event was not known before.
+    fn record_invalid_deposit(&mut self, source: EventSource, error: String) -> bool {
+        assert!(
+            !self.events_to_mint.contains_key(&source),
+            "attempted to mark an accepted event as invalid"
+        );
+        assert!(
+            !self.minted_events.contains_key(&source),
+            "attempted to mark a minted event {source:?} as invalid"
+        );
+
+        match self.invalid_events.entry(source) {
+            btree_map::Entry::Occupied(_) => false,
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(InvalidEventReason::InvalidDeposit(error));
+                true
+            }
+        }
+    }
+
+    fn record_successful_mint(&mut self, source: EventSource, mint_block_index: LedgerMintIndex) {
+        assert!(
+            !self.invalid_events.contains_key(&source),
+            "attempted to mint an event previously marked as invalid {source:?}"
+        );
+        let deposit_event = match self.events_to_mint.remove(&source) {
+            Some(event) => event,
+            None => panic!("attempted to mint tokens for an unknown event {source:?}"),
+        };
+        let token = deposit_event.token_contract_address;
+        let (_ledger_id, token_symbol) = self
+            .ledger_of(&token)
+            .unwrap_or_else(|| panic!("BUG: minted an unsupported token {token}"));
+        assert_eq!(
+            self.minted_events.insert(
+                source,
+                MintedEvent {
+                    deposit_event,
+                    mint_block_index,
+                    token_symbol,
+                },
+            ),
+            None,
+            "attempted to mint tokens twice for the same event {source:?}"
+        );
+        let balance = if token == Address::ZERO {
+            &mut self.matic_balance
+        } else {
+            self.erc20_balances.entry(token).or_default()
+        };
+        *balance = balance
+            .checked_add(deposit_event.value.into_inner())
+            .unwrap_or_else(|| {
+                panic!(
+                    "BUG: overflow when adding {} to the balance of {token}",
+                    deposit_event.value
+                )
+            });
+    }
+
+    /// Settles a withdrawal whose transaction was mined in a finalized block:
+    /// the minter paid the transaction fee and, if the transaction succeeded, the amount.
+    fn record_finalized_transaction(
+        &mut self,
+        withdrawal_id: LedgerBurnIndex,
+        receipt: TransactionReceipt,
+    ) {
+        let finalized = self
+            .polygon_transactions
+            .record_finalized_transaction(withdrawal_id, receipt);
+        // The amount of an ERC-20 withdrawal transaction is zero.
+        let mut debited = finalized.receipt.effective_transaction_fee();
+        if finalized.status() == TransactionStatus::Success {
+            debited = debited
+                .checked_add(finalized.transaction.transaction.amount)
+                .expect("BUG: overflow when adding the amount to the transaction fee");
+        }
+        let erc20_debited = match &finalized.request {
+            WithdrawalRequest::Erc20(request)
+                if finalized.status() == TransactionStatus::Success =>
+            {
+                Some((request.erc20_contract_address, request.withdrawal_amount))
+            }
+            _ => None,
+        };
+        self.matic_balance = self
+            .matic_balance
+            .checked_sub(debited.into_inner())
+            .unwrap_or_else(|| {
+                panic!("BUG: underflow when subtracting {debited} from the matic balance")
+            });
+        if let Some((token, amount)) = erc20_debited {
+            let balance = self.erc20_balances.entry(token).or_default();
+            *balance = balance.checked_sub(amount.into_inner()).unwrap_or_else(|| {
+                panic!("BUG: underflow when subtracting {amount} from the balance of {token}")
+            });
+        }
+    }
+
+    /// Quarantines a deposit whose minting outcome is unknown.
+    /// Returns `true` if the deposit was not quarantined before.
+    fn record_quarantined_deposit(&mut self, source: EventSource) -> bool {
+        self.events_to_mint.remove(&source);
+        match self.invalid_events.entry(source) {
+            btree_map::Entry::Occupied(_) => false,
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(InvalidEventReason::QuarantinedDeposit);
+                true
+            }
+        }
+    }
+
+    /// Gives up on an accepted deposit whose token is not supported anymore.
+    fn record_unsupported_deposit(&mut self, source: EventSource) {
+        let event = self
+            .events_to_mint
+            .remove(&source)
+            .unwrap_or_else(|| panic!("attempted to reject an unknown event {source:?}"));
+        assert_eq!(
+            self.invalid_events.insert(
+                source,
+                InvalidEventReason::InvalidDeposit(format!(
+                    "unsupported token {}",
+                    event.token_contract_address
+                )),
+            ),
+            None,
+            "attempted to reject an invalid event {source:?}"
+        );
+    }
+
+    fn record_skipped_block(&mut self, block_number: BlockNumber) {
+        assert!(
+            self.skipped_blocks.insert(block_number),
+            "BUG: block {} was already skipped",
+            block_number
+        );
+    }
+
+    /// Postpones the next attempt to scrape a skipped block after a failed one.
+    fn record_failed_skipped_block_retry(&mut self, block_number: BlockNumber, next_retry_at: u64) {
+        assert!(
+            self.skipped_blocks.contains(&block_number),
+            "BUG: block {block_number} was not skipped"
+        );
+        let retry = self.skipped_block_retries.entry(block_number).or_default();
+        retry.attempts = retry.attempts.saturating_add(1);
+        retry.next_retry_at = next_retry_at;
+    }
+
+    /// Schedules the next attempt to scrape a skipped block without delay.
+    fn record_forced_skipped_block_retry(&mut self, block_number: BlockNumber) {
+        assert!(
+            self.skipped_blocks.contains(&block_number),
+            "BUG: block {block_number} was not skipped"
+        );
+        self.skipped_block_retries
+            .entry(block_number)
+            .or_default()
+            .next_retry_at = 0;
+    }
+
+    /// Stops retrying a skipped block, either scraped or abandoned.
+    fn remove_skipped_block(&mut self, block_number: BlockNumber) {
+        assert!(
+            self.skipped_blocks.remove(&block_number),
+            "BUG: block {block_number} was not skipped"
+        );
+        self.skipped_block_retries.remove(&block_number);
+    }
+}
+
+/// Returns the minter's threshold ECDSA public key,
+/// fetching it from the management canister on first use.
+pub async fn lazy_call_ecdsa_public_key() -> Result<PublicKey, String> {
+    fn to_public_key(response: &EcdsaPublicKeyResponse) -> Result<PublicKey, String> {
+        PublicKey::deserialize_sec1(&response.public_key)
+            .map_err(|e| format!("failed to decode the minter's public key: {e:?}"))
+    }
+
+    if let Some(response) = read_state(|s| s.ecdsa_public_key.clone()) {
+        return to_public_key(&response);
+    }
+    let key_name = read_state(|s| s.ecdsa_key_name.clone());
+    let response = ecdsa_public_key(key_name, MAIN_DERIVATION_PATH)
+        .await
+        .map_err(|e| e.to_string())?;
+    let public_key = to_public_key(&response)?;
+    mutate_state(|s| s.ecdsa_public_key = Some(response));
+    Ok(public_key)
+}
+
+/// Returns the minter's address on Polygon.
+pub async fn minter_address() -> Result<Address, String> {
+    lazy_call_ecdsa_public_key()
+        .await
+        .map(|public_key| ecdsa_public_key_to_address(&public_key))
+}
+
+pub fn read_state<R>(f: impl FnOnce(&State) -> R) -> R {
+    STATE.with(|s| f(s.borrow().as_ref().expect("BUG: state is not initialized")))
+}
+
+/// Mutates (part of) the current state using `f`.
+///
+/// Panics if there is no state.
+pub fn mutate_state<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut State) -> R,
+{
+    STATE.with(|s| {
+        f(s.borrow_mut()
+            .as_mut()
+            .expect("BUG: state is not initialized"))
+    })
+}
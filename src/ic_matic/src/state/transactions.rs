@@ -0,0 +1,752 @@
+use candid::Principal;
+use minicbor::{Decode, Encode};
+use std::collections::{btree_map, BTreeMap, BTreeSet, VecDeque};
+
+use crate::log_types::{address::Address, hash::Hash};
+use crate::numeric::{
+    CkTokenAmount, LedgerBurnIndex, LedgerMintIndex, TransactionCount, TransactionNonce, Wei,
+};
+use crate::tx::{
+    Eip1559TransactionRequest, SignedEip1559TransactionRequest, TransactionReceipt,
+    TransactionStatus,
+};
+
+/// A request to withdraw MATIC, accepted once the corresponding icMATIC were burned.
+#[derive(Clone, Debug, Eq, PartialEq, Encode, Decode)]
+pub struct MaticWithdrawalRequest {
+    /// The amount of burned icMATIC, the transaction fee is deducted from it.
+    #[n(0)]
+    pub withdrawal_amount: Wei,
+    /// The address receiving the MATIC.
+    #[n(1)]
+    pub destination: Address,
+    /// The transaction index on the icMATIC ledger that burned the tokens.
+    #[cbor(n(2), with = "crate::cbor::id")]
+    pub ledger_burn_index: LedgerBurnIndex,
+    /// The owner of the burned icMATIC.
+    #[cbor(n(3), with = "crate::cbor::principal")]
+    pub from: Principal,
+    /// The canister time at which the minter accepted the request.
+    #[n(4)]
+    pub created_at: u64,
+}
+
+/// A request to withdraw an ERC-20 token through the helper contract, accepted once
+/// the icMATIC paying for the transaction fee and the icERC20 tokens were burned.
+#[derive(Clone, Debug, Eq, PartialEq, Encode, Decode)]
+pub struct Erc20WithdrawalRequest {
+    /// The amount of burned icMATIC, the maximum fee of the withdrawal transactions.
+    #[n(0)]
+    pub max_transaction_fee: Wei,
+    /// The amount of burned icERC20, sent in full to the destination.
+    #[n(1)]
+    pub withdrawal_amount: CkTokenAmount,
+    /// The address receiving the tokens.
+    #[n(2)]
+    pub destination: Address,
+    /// The transaction index on the icMATIC ledger that burned the fee,
+    /// which identifies the withdrawal request.
+    #[cbor(n(3), with = "crate::cbor::id")]
+    pub icmatic_ledger_burn_index: LedgerBurnIndex,
+    /// The address of the token contract.
+    #[n(4)]
+    pub erc20_contract_address: Address,
+    /// The ledger of the burned icERC20.
+    #[cbor(n(5), with = "crate::cbor::principal")]
+    pub erc20_ledger_id: Principal,
+    /// The transaction index on the icERC20 ledger that burned the tokens.
+    #[cbor(n(6), with = "crate::cbor::id")]
+    pub erc20_ledger_burn_index: LedgerBurnIndex,
+    /// The owner of the burned tokens.
+    #[cbor(n(7), with = "crate::cbor::principal")]
+    pub from: Principal,
+    /// The canister time at which the minter accepted the request.
+    #[n(8)]
+    pub created_at: u64,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum WithdrawalRequest {
+    Matic(MaticWithdrawalRequest),
+    Erc20(Erc20WithdrawalRequest),
+}
+
+impl WithdrawalRequest {
+    /// The index of the icMATIC burn transaction, which identifies the withdrawal.
+    pub fn withdrawal_id(&self) -> LedgerBurnIndex {
+        match self {
+            WithdrawalRequest::Matic(request) => request.ledger_burn_index,
+            WithdrawalRequest::Erc20(request) => request.icmatic_ledger_burn_index,
+        }
+    }
+
+    pub fn from(&self) -> Principal {
+        match self {
+            WithdrawalRequest::Matic(request) => request.from,
+            WithdrawalRequest::Erc20(request) => request.from,
+        }
+    }
+
+    pub fn created_at(&self) -> u64 {
+        match self {
+            WithdrawalRequest::Matic(request) => request.created_at,
+            WithdrawalRequest::Erc20(request) => request.created_at,
+        }
+    }
+}
+
+impl From<MaticWithdrawalRequest> for WithdrawalRequest {
+    fn from(request: MaticWithdrawalRequest) -> Self {
+        WithdrawalRequest::Matic(request)
+    }
+}
+
+impl From<Erc20WithdrawalRequest> for WithdrawalRequest {
+    fn from(request: Erc20WithdrawalRequest) -> Self {
+        WithdrawalRequest::Erc20(request)
+    }
+}
+
+/// A withdrawal whose transaction was mined in a finalized block.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FinalizedWithdrawal {
+    pub request: WithdrawalRequest,
+    /// The transaction that was mined, among all the signed ones.
+    pub transaction: SignedEip1559TransactionRequest,
+    pub receipt: TransactionReceipt,
+}
+
+impl FinalizedWithdrawal {
+    pub fn status(&self) -> TransactionStatus {
+        self.receipt.status
+    }
+}
+
+/// A withdrawal request for which no transaction could ever be sent.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AbandonedWithdrawal {
+    pub request: WithdrawalRequest,
+    pub reason: String,
+}
+
+/// Identifies the tokens to reimburse for a withdrawal: an ERC-20 withdrawal burned
+/// both icMATIC, for the transaction fee, and icERC20.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum ReimbursementIndex {
+    IcMatic {
+        withdrawal_id: LedgerBurnIndex,
+    },
+    IcErc20 {
+        withdrawal_id: LedgerBurnIndex,
+        ledger_id: Principal,
+    },
+}
+
+impl ReimbursementIndex {
+    pub fn withdrawal_id(&self) -> LedgerBurnIndex {
+        match self {
+            ReimbursementIndex::IcMatic { withdrawal_id } => *withdrawal_id,
+            ReimbursementIndex::IcErc20 { withdrawal_id, .. } => *withdrawal_id,
+        }
+    }
+}
+
+/// The stage a withdrawal request reached, see [`PolygonTransactions`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WithdrawalStage<'a> {
+    Pending,
+    Created(&'a Eip1559TransactionRequest),
+    /// The last signed transaction, possibly being replaced.
+    Signed(&'a SignedEip1559TransactionRequest),
+    Finalized(&'a FinalizedWithdrawal),
+    Abandoned(&'a AbandonedWithdrawal),
+}
+
+/// Tokens to mint back to the owner of a withdrawal request that did not go through.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReimbursementRequest {
+    pub withdrawal_id: LedgerBurnIndex,
+    /// The burned amount minus the fees actually spent, in the smallest unit of the token.
+    pub reimbursed_amount: Wei,
+    pub to: Principal,
+    /// The failed transaction, if any was mined.
+    pub transaction_hash: Option<Hash>,
+}
+
+/// A reimbursement minted on the ledger of the burned tokens.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Reimbursed {
+    pub withdrawal_id: LedgerBurnIndex,
+    pub reimbursed_amount: Wei,
+    pub reimbursed_in_block: LedgerMintIndex,
+    pub transaction_hash: Option<Hash>,
+}
+
+/// Withdrawal requests and the Polygon transactions issued to fulfill them.
+///
+/// A withdrawal request goes through the following stages:
+/// 1. it is pending until the minter creates a transaction for it;
+/// 2. the transaction is created, with the next available nonce, but not signed;
+/// 3. the transaction is signed and (re)sent to the network;
+/// 4. one of its transactions is mined in a finalized block, the withdrawal either
+///    succeeded or failed depending on the receipt status.
+///
+/// A withdrawal whose transaction failed, or for which no transaction could be sent,
+/// is reimbursed exactly once: the burned tokens minus the spent fees are minted back.
+///
+/// A sent transaction that is stuck because of too low fees is replaced by a transaction
+/// with the same nonce and bumped fees, which goes through stages 2 and 3 again.
+/// Every signed transaction of a withdrawal is kept since any of them may end up mined.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PolygonTransactions {
+    pending_withdrawal_requests: VecDeque<WithdrawalRequest>,
+    processed_withdrawal_requests: BTreeMap<LedgerBurnIndex, WithdrawalRequest>,
+    created_tx: BTreeMap<LedgerBurnIndex, Eip1559TransactionRequest>,
+    sent_tx: BTreeMap<LedgerBurnIndex, Vec<SignedEip1559TransactionRequest>>,
+    /// Hash of the last signed transaction of each withdrawal that the providers accepted.
+    accepted_tx: BTreeMap<LedgerBurnIndex, Hash>,
+    finalized_tx: BTreeMap<LedgerBurnIndex, FinalizedWithdrawal>,
+    abandoned_withdrawal_requests: BTreeMap<LedgerBurnIndex, AbandonedWithdrawal>,
+    reimbursement_requests: BTreeMap<ReimbursementIndex, ReimbursementRequest>,
+    reimbursed: BTreeMap<ReimbursementIndex, Reimbursed>,
+    quarantined_reimbursements: BTreeSet<ReimbursementIndex>,
+    next_nonce: TransactionNonce,
+}
+
+impl PolygonTransactions {
+    pub fn new(next_nonce: TransactionNonce) -> Self {
+        Self {
+            pending_withdrawal_requests: VecDeque::new(),
+            processed_withdrawal_requests: BTreeMap::new(),
+            created_tx: BTreeMap::new(),
+            sent_tx: BTreeMap::new(),
+            accepted_tx: BTreeMap::new(),
+            finalized_tx: BTreeMap::new(),
+            abandoned_withdrawal_requests: BTreeMap::new(),
+            reimbursement_requests: BTreeMap::new(),
+            reimbursed: BTreeMap::new(),
+            quarantined_reimbursements: BTreeSet::new(),
+            next_nonce,
+        }
+    }
+
+    pub fn next_nonce(&self) -> TransactionNonce {
+        self.next_nonce
+    }
+
+    /// Returns `true` if there is no withdrawal request to process.
+    pub fn is_empty(&self) -> bool {
+        self.pending_withdrawal_requests.is_empty() && self.processed_withdrawal_requests.is_empty()
+    }
+
+    pub fn record_withdrawal_request<R: Into<WithdrawalRequest>>(&mut self, request: R) {
+        let request = request.into();
+        let burn_index = request.withdrawal_id();
+        assert!(
+            !self.contains(&burn_index),
+            "BUG: duplicate withdrawal request with burn index {burn_index}"
+        );
+        self.pending_withdrawal_requests.push_back(request);
+    }
+
+    /// Returns the oldest pending withdrawal requests, at most `limit` of them.
+    pub fn withdrawal_requests_batch(&self, limit: usize) -> Vec<WithdrawalRequest> {
+        self.pending_withdrawal_requests
+            .iter()
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn record_created_transaction(
+        &mut self,
+        withdrawal_id: LedgerBurnIndex,
+        transaction: Eip1559TransactionRequest,
+    ) {
+        let position = self
+            .pending_withdrawal_requests
+            .iter()
+            .position(|request| request.withdrawal_id() == withdrawal_id)
+            .unwrap_or_else(|| panic!("BUG: no pending withdrawal request {withdrawal_id}"));
+        assert_eq!(
+            transaction.nonce, self.next_nonce,
+            "BUG: transaction nonce does not match the next nonce"
+        );
+        let request = self
+            .pending_withdrawal_requests
+            .remove(position)
+            .expect("BUG: position is valid");
+        self.processed_withdrawal_requests
+            .insert(withdrawal_id, request);
+        self.next_nonce = self
+            .next_nonce
+            .checked_increment()
+            .expect("BUG: transaction nonce overflow");
+        assert_eq!(self.created_tx.insert(withdrawal_id, transaction), None);
+    }
+
+    /// Records a transaction replacing the last sent transaction of a withdrawal.
+    pub fn record_replaced_transaction(
+        &mut self,
+        withdrawal_id: LedgerBurnIndex,
+        transaction: Eip1559TransactionRequest,
+    ) {
+        let last_sent_tx = self
+            .last_sent_transaction(&withdrawal_id)
+            .unwrap_or_else(|| panic!("BUG: no sent transaction to replace for {withdrawal_id}"));
+        assert_eq!(
+            last_sent_tx.transaction.nonce, transaction.nonce,
+            "BUG: a replacement transaction must have the same nonce"
+        );
+        assert_eq!(
+            self.created_tx.insert(withdrawal_id, transaction),
+            None,
+            "BUG: the transaction of {withdrawal_id} is already being replaced"
+        );
+    }
+
+    pub fn transactions_to_sign(&self) -> Vec<(LedgerBurnIndex, Eip1559TransactionRequest)> {
+        self.created_tx
+            .iter()
+            .map(|(withdrawal_id, tx)| (*withdrawal_id, tx.clone()))
+            .collect()
+    }
+
+    pub fn record_signed_transaction(
+        &mut self,
+        withdrawal_id: LedgerBurnIndex,
+        signed_transaction: SignedEip1559TransactionRequest,
+    ) {
+        let created_tx = self
+            .created_tx
+            .remove(&withdrawal_id)
+            .unwrap_or_else(|| panic!("BUG: no created transaction for {withdrawal_id}"));
+        assert_eq!(
+            created_tx, signed_transaction.transaction,
+            "BUG: signed transaction does not match the created one"
+        );
+        self.sent_tx
+            .entry(withdrawal_id)
+            .or_default()
+            .push(signed_transaction);
+    }
+
+    /// Records that the JSON-RPC providers accepted the given signed transaction.
+    pub fn record_sent_transaction(
+        &mut self,
+        withdrawal_id: LedgerBurnIndex,
+        transaction_hash: Hash,
+    ) {
+        assert!(
+            self.sent_tx
+                .get(&withdrawal_id)
+                .is_some_and(|txs| txs.iter().any(|tx| tx.hash() == transaction_hash)),
+            "BUG: transaction {transaction_hash} was not signed for {withdrawal_id}"
+        );
+        self.accepted_tx.insert(withdrawal_id, transaction_hash);
+    }
+
+    /// Returns the hash of the last transaction of the withdrawal that the providers accepted.
+    pub fn sent_transaction_hash(&self, withdrawal_id: &LedgerBurnIndex) -> Option<&Hash> {
+        self.accepted_tx.get(withdrawal_id)
+    }
+
+    /// Returns the last signed transaction of every withdrawal whose nonce
+    /// was not yet used by a transaction in the latest block.
+    pub fn transactions_to_send(
+        &self,
+        latest_transaction_count: TransactionCount,
+    ) -> Vec<(LedgerBurnIndex, SignedEip1559TransactionRequest)> {
+        let latest_nonce = latest_transaction_count.change_units();
+        self.sent_tx
+            .iter()
+            .filter_map(|(withdrawal_id, txs)| Some((*withdrawal_id, txs.last()?.clone())))
+            .filter(|(_, tx)| tx.transaction.nonce >= latest_nonce)
+            .collect()
+    }
+
+    /// Returns the withdrawal requests whose last sent transaction is not mined yet
+    /// and is not already being replaced, together with that transaction.
+    pub fn transactions_to_resubmit(
+        &self,
+        latest_transaction_count: TransactionCount,
+    ) -> Vec<(WithdrawalRequest, Eip1559TransactionRequest)> {
+        self.transactions_to_send(latest_transaction_count)
+            .into_iter()
+            .filter(|(withdrawal_id, _)| !self.created_tx.contains_key(withdrawal_id))
+            .map(|(withdrawal_id, tx)| {
+                let request = self
+                    .processed_withdrawal_requests
+                    .get(&withdrawal_id)
+                    .unwrap_or_else(|| panic!("BUG: no withdrawal request {withdrawal_id}"));
+                (request.clone(), tx.transaction)
+            })
+            .collect()
+    }
+
+    /// Returns the sent transactions of every withdrawal whose nonce was used
+    /// by a transaction in a finalized block, i.e. one of them must have a receipt.
+    pub fn transactions_to_finalize(
+        &self,
+        finalized_transaction_count: TransactionCount,
+    ) -> Vec<(LedgerBurnIndex, Vec<SignedEip1559TransactionRequest>)> {
+        let finalized_nonce = finalized_transaction_count.change_units();
+        self.sent_tx
+            .iter()
+            .filter(|(_, txs)| {
+                txs.last()
+                    .is_some_and(|tx| tx.transaction.nonce < finalized_nonce)
+            })
+            .map(|(withdrawal_id, txs)| (*withdrawal_id, txs.clone()))
+            .collect()
+    }
+
+    /// Records the receipt of the mined transaction of a withdrawal, which is then settled.
+    pub fn record_finalized_transaction(
+        &mut self,
+        withdrawal_id: LedgerBurnIndex,
+        receipt: TransactionReceipt,
+    ) -> &FinalizedWithdrawal {
+        let sent_txs = self
+            .sent_tx
+            .remove(&withdrawal_id)
+            .unwrap_or_else(|| panic!("BUG: no sent transaction for {withdrawal_id}"));
+        self.accepted_tx.remove(&withdrawal_id);
+        let transaction = sent_txs
+            .into_iter()
+            .find(|tx| tx.hash() == receipt.transaction_hash)
+            .unwrap_or_else(|| {
+                panic!(
+                    "BUG: transaction {} was not sent for {withdrawal_id}",
+                    receipt.transaction_hash
+                )
+            });
+        // A replacement that was not signed yet is obsolete.
+        self.created_tx.remove(&withdrawal_id);
+        let request = self
+            .processed_withdrawal_requests
+            .remove(&withdrawal_id)
+            .unwrap_or_else(|| panic!("BUG: no withdrawal request {withdrawal_id}"));
+        if receipt.status == TransactionStatus::Failure {
+            let transaction_hash = Some(receipt.transaction_hash);
+            match &request {
+                WithdrawalRequest::Matic(request) => {
+                    self.record_reimbursement_request(
+                        ReimbursementIndex::IcMatic { withdrawal_id },
+                        ReimbursementRequest {
+                            withdrawal_id,
+                            reimbursed_amount: request
+                                .withdrawal_amount
+                                .checked_sub(receipt.effective_transaction_fee())
+                                .unwrap_or(Wei::ZERO),
+                            to: request.from,
+                            transaction_hash,
+                        },
+                    );
+                }
+                WithdrawalRequest::Erc20(request) => {
+                    let unspent_fee = request
+                        .max_transaction_fee
+                        .checked_sub(receipt.effective_transaction_fee())
+                        .unwrap_or(Wei::ZERO);
+                    if unspent_fee > Wei::ZERO {
+                        self.record_reimbursement_request(
+                            ReimbursementIndex::IcMatic { withdrawal_id },
+                            ReimbursementRequest {
+                                withdrawal_id,
+                                reimbursed_amount: unspent_fee,
+                                to: request.from,
+                                transaction_hash,
+                            },
+                        );
+                    }
+                    self.record_reimbursement_request(
+                        ReimbursementIndex::IcErc20 {
+                            withdrawal_id,
+                            ledger_id: request.erc20_ledger_id,
+                        },
+                        ReimbursementRequest {
+                            withdrawal_id,
+                            reimbursed_amount: request.withdrawal_amount.change_units(),
+                            to: request.from,
+                            transaction_hash,
+                        },
+                    );
+                }
+            }
+        }
+        match self.finalized_tx.entry(withdrawal_id) {
+            btree_map::Entry::Occupied(_) => {
+                panic!("BUG: withdrawal {withdrawal_id} is already finalized")
+            }
+            btree_map::Entry::Vacant(entry) => entry.insert(FinalizedWithdrawal {
+                request,
+                transaction,
+                receipt,
+            }),
+        }
+    }
+
+    /// Gives up on a pending withdrawal request, whose burned tokens are reimbursed in full.
+    pub fn record_abandoned_withdrawal_request(
+        &mut self,
+        withdrawal_id: LedgerBurnIndex,
+        reason: String,
+    ) {
+        let position = self
+            .pending_withdrawal_requests
+            .iter()
+            .position(|request| request.withdrawal_id() == withdrawal_id)
+            .unwrap_or_else(|| panic!("BUG: no pending withdrawal request {withdrawal_id}"));
+        let request = self
+            .pending_withdrawal_requests
+            .remove(position)
+            .expect("BUG: position is valid");
+        match &request {
+            WithdrawalRequest::Matic(request) => {
+                self.record_reimbursement_request(
+                    ReimbursementIndex::IcMatic { withdrawal_id },
+                    ReimbursementRequest {
+                        withdrawal_id,
+                        reimbursed_amount: request.withdrawal_amount,
+                        to: request.from,
+                        transaction_hash: None,
+                    },
+                );
+            }
+            WithdrawalRequest::Erc20(request) => {
+                self.record_reimbursement_request(
+                    ReimbursementIndex::IcMatic { withdrawal_id },
+                    ReimbursementRequest {
+                        withdrawal_id,
+                        reimbursed_amount: request.max_transaction_fee,
+                        to: request.from,
+                        transaction_hash: None,
+                    },
+                );
+                self.record_reimbursement_request(
+                    ReimbursementIndex::IcErc20 {
+                        withdrawal_id,
+                        ledger_id: request.erc20_ledger_id,
+                    },
+                    ReimbursementRequest {
+                        withdrawal_id,
+                        reimbursed_amount: request.withdrawal_amount.change_units(),
+                        to: request.from,
+                        transaction_hash: None,
+                    },
+                );
+            }
+        }
+        assert_eq!(
+            self.abandoned_withdrawal_requests
+                .insert(withdrawal_id, AbandonedWithdrawal { request, reason }),
+            None
+        );
+    }
+
+    /// Records the reimbursement of the icMATIC burned to pay the fee of an ERC-20
+    /// withdrawal request whose icERC20 could not be burned.
+    pub fn record_failed_erc20_withdrawal_request(&mut self, request: ReimbursementRequest) {
+        self.record_reimbursement_request(
+            ReimbursementIndex::IcMatic {
+                withdrawal_id: request.withdrawal_id,
+            },
+            request,
+        );
+    }
+
+    fn record_reimbursement_request(
+        &mut self,
+        index: ReimbursementIndex,
+        request: ReimbursementRequest,
+    ) {
+        assert!(
+            !self.reimbursed.contains_key(&index)
+                && !self.quarantined_reimbursements.contains(&index),
+            "BUG: {index:?} was already reimbursed"
+        );
+        assert_eq!(
+            self.reimbursement_requests.insert(index, request),
+            None,
+            "BUG: duplicate reimbursement request for {index:?}"
+        );
+    }
+
+    pub fn reimbursement_requests(&self) -> Vec<(ReimbursementIndex, ReimbursementRequest)> {
+        self.reimbursement_requests
+            .iter()
+            .map(|(index, request)| (*index, request.clone()))
+            .collect()
+    }
+
+    pub fn record_reimbursed(
+        &mut self,
+        index: ReimbursementIndex,
+        reimbursed_in_block: LedgerMintIndex,
+    ) {
+        let request = self
+            .reimbursement_requests
+            .remove(&index)
+            .unwrap_or_else(|| panic!("BUG: no reimbursement request for {index:?}"));
+        let withdrawal_id = request.withdrawal_id;
+        assert_eq!(
+            self.reimbursed.insert(
+                index,
+                Reimbursed {
+                    withdrawal_id,
+                    reimbursed_amount: request.reimbursed_amount,
+                    reimbursed_in_block,
+                    transaction_hash: request.transaction_hash,
+                },
+            ),
+            None,
+            "BUG: {index:?} was already reimbursed"
+        );
+    }
+
+    /// Stops processing a reimbursement whose minting outcome is unknown.
+    pub fn record_quarantined_reimbursement(&mut self, index: ReimbursementIndex) {
+        self.reimbursement_requests.remove(&index);
+        self.quarantined_reimbursements.insert(index);
+    }
+
+    pub fn reimbursed(&self, index: &ReimbursementIndex) -> Option<&Reimbursed> {
+        self.reimbursed.get(index)
+    }
+
+    pub fn finalized_withdrawal(
+        &self,
+        withdrawal_id: &LedgerBurnIndex,
+    ) -> Option<&FinalizedWithdrawal> {
+        self.finalized_tx.get(withdrawal_id)
+    }
+
+    /// Returns the withdrawal request with the given id and the stage it reached.
+    pub fn withdrawal_stage(
+        &self,
+        withdrawal_id: &LedgerBurnIndex,
+    ) -> Option<(&WithdrawalRequest, WithdrawalStage<'_>)> {
+        if let Some(request) = self
+            .pending_withdrawal_requests
+            .iter()
+            .find(|request| &request.withdrawal_id() == withdrawal_id)
+        {
+            return Some((request, WithdrawalStage::Pending));
+        }
+        if let Some(request) = self.processed_withdrawal_requests.get(withdrawal_id) {
+            let stage = match self.last_sent_transaction(withdrawal_id) {
+                Some(tx) => WithdrawalStage::Signed(tx),
+                None => WithdrawalStage::Created(
+                    self.created_tx
+                        .get(withdrawal_id)
+                        .unwrap_or_else(|| panic!("BUG: no transaction for {withdrawal_id}")),
+                ),
+            };
+            return Some((request, stage));
+        }
+        if let Some(finalized) = self.finalized_tx.get(withdrawal_id) {
+            return Some((&finalized.request, WithdrawalStage::Finalized(finalized)));
+        }
+        self.abandoned_withdrawal_requests
+            .get(withdrawal_id)
+            .map(|abandoned| (&abandoned.request, WithdrawalStage::Abandoned(abandoned)))
+    }
+
+    /// Returns all the withdrawal requests, whatever the stage they reached.
+    pub fn withdrawal_requests(&self) -> impl Iterator<Item = &WithdrawalRequest> {
+        self.pending_withdrawal_requests
+            .iter()
+            .chain(self.processed_withdrawal_requests.values())
+            .chain(
+                self.finalized_tx
+                    .values()
+                    .map(|finalized| &finalized.request),
+            )
+            .chain(
+                self.abandoned_withdrawal_requests
+                    .values()
+                    .map(|abandoned| &abandoned.request),
+            )
+    }
+
+    /// Returns the reimbursements of a withdrawal once they were all minted.
+    pub fn completed_reimbursements(
+        &self,
+        withdrawal_id: &LedgerBurnIndex,
+    ) -> Option<Vec<(ReimbursementIndex, &Reimbursed)>> {
+        let is_of_withdrawal = |index: &ReimbursementIndex| &index.withdrawal_id() == withdrawal_id;
+        if self.reimbursement_requests.keys().any(is_of_withdrawal)
+            || self.quarantined_reimbursements.iter().any(is_of_withdrawal)
+        {
+            return None;
+        }
+        let reimbursed: Vec<_> = self
+            .reimbursed
+            .iter()
+            .filter(|(index, _)| is_of_withdrawal(index))
+            .map(|(index, reimbursed)| (*index, reimbursed))
+            .collect();
+        (!reimbursed.is_empty()).then_some(reimbursed)
+    }
+
+    /// Moves the next nonce forward to the number of finalized transactions of the minter,
+    /// e.g. when transactions were sent from the minter's address by other means.
+    pub fn record_next_nonce(&mut self, next_nonce: TransactionNonce) {
+        assert!(
+            self.has_no_transaction_in_flight(),
+            "BUG: cannot update the next nonce while transactions are in flight"
+        );
+        assert!(
+            next_nonce > self.next_nonce,
+            "BUG: the next nonce can only move forward"
+        );
+        self.next_nonce = next_nonce;
+    }
+
+    /// Returns `true` if no transaction was created and not yet finalized.
+    pub fn has_no_transaction_in_flight(&self) -> bool {
+        self.created_tx.is_empty() && self.sent_tx.is_empty()
+    }
+
+    /// Returns true if a withdrawal or a reimbursement of the given icERC20 ledger
+    /// is not yet completed.
+    pub fn has_pending_erc20_operations(&self, ledger_id: &Principal) -> bool {
+        let is_of_ledger = |request: &WithdrawalRequest| match request {
+            WithdrawalRequest::Matic(_) => false,
+            WithdrawalRequest::Erc20(request) => &request.erc20_ledger_id == ledger_id,
+        };
+        self.pending_withdrawal_requests.iter().any(is_of_ledger)
+            || self
+                .processed_withdrawal_requests
+                .values()
+                .any(is_of_ledger)
+            || self.reimbursement_requests.keys().any(|index| match index {
+                ReimbursementIndex::IcMatic { .. } => false,
+                ReimbursementIndex::IcErc20 {
+                    ledger_id: index_ledger_id,
+                    ..
+                } => index_ledger_id == ledger_id,
+            })
+    }
+
+    fn last_sent_transaction(
+        &self,
+        withdrawal_id: &LedgerBurnIndex,
+    ) -> Option<&SignedEip1559TransactionRequest> {
+        self.sent_tx.get(withdrawal_id).and_then(|txs| txs.last())
+    }
+
+    fn contains(&self, withdrawal_id: &LedgerBurnIndex) -> bool {
+        self.pending_withdrawal_requests
+            .iter()
+            .any(|request| &request.withdrawal_id() == withdrawal_id)
+            || self
+                .processed_withdrawal_requests
+                .contains_key(withdrawal_id)
+            || self.finalized_tx.contains_key(withdrawal_id)
+            || self
+                .abandoned_withdrawal_requests
+                .contains_key(withdrawal_id)
+    }
+}
@@ -0,0 +1,63 @@
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{DefaultMemoryImpl, StableLog, Storable};
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+use crate::state::event::{Event, EventType};
+
+const LOG_INDEX_MEMORY_ID: MemoryId = MemoryId::new(0);
+const LOG_DATA_MEMORY_ID: MemoryId = MemoryId::new(1);
+
+type VMem = VirtualMemory<DefaultMemoryImpl>;
+type EventLog = StableLog<Event, VMem, VMem>;
+
+impl Storable for Event {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        let mut buf = vec![];
+        minicbor::encode(self, &mut buf).expect("event encoding should always succeed");
+        Cow::Owned(buf)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        minicbor::decode(bytes.as_ref())
+            .unwrap_or_else(|e| panic!("failed to decode event bytes {}: {e}", hex::encode(bytes)))
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
+        MemoryManager::init(DefaultMemoryImpl::default())
+    );
+
+    /// The log of the icMATIC state modifications.
+    static EVENTS: RefCell<EventLog> = MEMORY_MANAGER
+        .with(|m|
+              RefCell::new(
+                  StableLog::init(
+                      m.borrow().get(LOG_INDEX_MEMORY_ID),
+                      m.borrow().get(LOG_DATA_MEMORY_ID)
+                  ).expect("failed to initialize stable log")
+              )
+        );
+}
+
+/// Appends the event to the event log.
+pub fn record_event(payload: EventType) {
+    let event = Event {
+        timestamp: ic_cdk::api::time(),
+        payload,
+    };
+    EVENTS
+        .with(|events| events.borrow().append(&event))
+        .expect("recording an event should succeed");
+}
+
+pub fn with_event_iter<F, R>(f: F) -> R
+where
+    F: for<'a> FnOnce(Box<dyn Iterator<Item = Event> + 'a>) -> R,
+{
+    EVENTS.with(|events| f(Box::new(events.borrow().iter())))
+}
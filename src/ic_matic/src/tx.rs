@@ -0,0 +1,526 @@
+use ic_canister_log::log;
+use minicbor::{Decode, Encode};
+use rlp::RlpStream;
+use serde_bytes::ByteBuf;
+use std::cmp::max;
+use std::fmt;
+
+use crate::evm_rpc_canister::{BlockTag, FeeHistory, FeeHistoryArgs};
+use crate::guard::TimerGuard;
+use crate::log_types::{address::Address, hash::Hash};
+use crate::logs::{DEBUG, INFO};
+use crate::management::{sign_with_ecdsa_recoverable, MAIN_DERIVATION_PATH};
+use crate::numeric::{BlockNumber, GasAmount, TransactionNonce, Wei, WeiPerGas};
+use crate::rpc_client::RpcClient;
+use crate::state::{lazy_call_ecdsa_public_key, mutate_state, read_state, TaskType};
+
+/// EIP-2718 transaction type of EIP-1559 transactions.
+const EIP1559_TX_ID: u8 = 2;
+
+/// Polygon PoS validators ignore transactions with a priority fee below 30 gwei.
+pub const MIN_MAX_PRIORITY_FEE_PER_GAS: WeiPerGas = WeiPerGas::new(30_000_000_000);
+
+/// Number of blocks, up to the latest one, whose fees are used for the estimate.
+const FEE_HISTORY_BLOCK_COUNT: u128 = 5;
+
+/// Percentile of the priority fees paid in each block, weighted by gas used.
+const FEE_HISTORY_REWARD_PERCENTILE: u8 = 20;
+
+/// Age after which a cached gas fee estimate is refreshed before being used.
+const GAS_FEE_ESTIMATE_MAX_AGE_NS: u64 = 60_000_000_000;
+
+/// A 32-byte storage slot of a contract.
+#[derive(Clone, Copy, PartialEq, Eq, Encode, Decode)]
+#[cbor(transparent)]
+pub struct StorageKey(#[cbor(n(0), with = "minicbor::bytes")] pub [u8; 32]);
+
+impl fmt::Debug for StorageKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{}", hex::encode(self.0))
+    }
+}
+
+impl rlp::Encodable for StorageKey {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.append(&self.0.as_ref());
+    }
+}
+
+/// The storage slots of a contract that the transaction plans to access.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct AccessListItem {
+    #[n(0)]
+    pub address: Address,
+    #[n(1)]
+    pub storage_keys: Vec<StorageKey>,
+}
+
+impl rlp::Encodable for AccessListItem {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(2);
+        s.append(&self.address);
+        s.append_list(&self.storage_keys);
+    }
+}
+
+/// The EIP-2930 access list of a transaction, see <https://eips.ethereum.org/EIPS/eip-2930>.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessList(pub Vec<AccessListItem>);
+
+impl AccessList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl rlp::Encodable for AccessList {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.append_list(&self.0);
+    }
+}
+
+impl<C> Encode<C> for AccessList {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut minicbor::Encoder<W>,
+        ctx: &mut C,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        e.encode_with(&self.0, ctx)?;
+        Ok(())
+    }
+}
+
+impl<'b, C> Decode<'b, C> for AccessList {
+    fn decode(d: &mut minicbor::Decoder<'b>, ctx: &mut C) -> Result<Self, minicbor::decode::Error> {
+        d.decode_with(ctx).map(Self)
+    }
+}
+
+/// An unsigned EIP-1559 transaction, see <https://eips.ethereum.org/EIPS/eip-1559>.
+#[derive(Clone, Debug, Eq, PartialEq, Encode, Decode)]
+pub struct Eip1559TransactionRequest {
+    #[n(0)]
+    pub chain_id: u64,
+    #[n(1)]
+    pub nonce: TransactionNonce,
+    #[n(2)]
+    pub max_priority_fee_per_gas: WeiPerGas,
+    #[n(3)]
+    pub max_fee_per_gas: WeiPerGas,
+    #[n(4)]
+    pub gas_limit: GasAmount,
+    #[n(5)]
+    pub destination: Address,
+    #[n(6)]
+    pub amount: Wei,
+    #[cbor(n(7), with = "minicbor::bytes")]
+    pub data: Vec<u8>,
+    #[n(8)]
+    pub access_list: AccessList,
+}
+
+impl rlp::Encodable for Eip1559TransactionRequest {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_unbounded_list();
+        self.rlp_inner(s);
+        s.finalize_unbounded_list();
+    }
+}
+
+impl Eip1559TransactionRequest {
+    fn rlp_inner(&self, s: &mut RlpStream) {
+        s.append(&self.chain_id);
+        s.append(&self.nonce);
+        s.append(&self.max_priority_fee_per_gas);
+        s.append(&self.max_fee_per_gas);
+        s.append(&self.gas_limit);
+        s.append(&self.destination);
+        s.append(&self.amount);
+        s.append(&self.data);
+        s.append(&self.access_list);
+    }
+
+    /// The typed transaction payload `0x02 || rlp([chain_id, ..., access_list])`.
+    pub fn encoded(&self) -> Vec<u8> {
+        let mut bytes = vec![EIP1559_TX_ID];
+        bytes.extend_from_slice(&rlp::encode(self));
+        bytes
+    }
+
+    /// Hash of the unsigned transaction, i.e. the message signed by the minter.
+    pub fn hash(&self) -> Hash {
+        Hash(ic_crypto_sha3::Keccak256::hash(self.encoded()))
+    }
+
+    /// The maximum fee the transaction can cost, paid by the minter.
+    pub fn max_transaction_fee(&self) -> Wei {
+        self.max_fee_per_gas
+            .transaction_cost(self.gas_limit)
+            .unwrap_or(Wei::MAX)
+    }
+
+    /// Signs the transaction with the minter's threshold ECDSA key.
+    pub async fn sign(self) -> Result<SignedEip1559TransactionRequest, String> {
+        let hash = self.hash();
+        let public_key = lazy_call_ecdsa_public_key().await?;
+        let key_name = read_state(|s| s.ecdsa_key_name.clone());
+        let signature =
+            sign_with_ecdsa_recoverable(key_name, MAIN_DERIVATION_PATH, &public_key, hash.0)
+                .await
+                .map_err(|e| format!("failed to sign transaction {hash}: {e}"))?;
+        Ok(SignedEip1559TransactionRequest {
+            transaction: self,
+            signature: Eip1559Signature {
+                signature_y_parity: signature.v == 1,
+                r: signature.r,
+                s: signature.s,
+            },
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Encode, Decode)]
+pub struct Eip1559Signature {
+    #[n(0)]
+    pub signature_y_parity: bool,
+    #[cbor(n(1), with = "crate::cbor::u256")]
+    pub r: ethnum::u256,
+    #[cbor(n(2), with = "crate::cbor::u256")]
+    pub s: ethnum::u256,
+}
+
+/// An EIP-1559 transaction together with the minter's signature.
+#[derive(Clone, Debug, Eq, PartialEq, Encode, Decode)]
+pub struct SignedEip1559TransactionRequest {
+    #[n(0)]
+    pub transaction: Eip1559TransactionRequest,
+    #[n(1)]
+    pub signature: Eip1559Signature,
+}
+
+impl rlp::Encodable for SignedEip1559TransactionRequest {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_unbounded_list();
+        self.transaction.rlp_inner(s);
+        s.append(&self.signature.signature_y_parity);
+        append_u256(s, self.signature.r);
+        append_u256(s, self.signature.s);
+        s.finalize_unbounded_list();
+    }
+}
+
+impl SignedEip1559TransactionRequest {
+    /// The typed transaction envelope `0x02 || rlp([chain_id, ..., access_list, y_parity, r, s])`,
+    /// as sent to the network.
+    pub fn encoded(&self) -> Vec<u8> {
+        let mut bytes = vec![EIP1559_TX_ID];
+        bytes.extend_from_slice(&rlp::encode(self));
+        bytes
+    }
+
+    pub fn raw_transaction_hex(&self) -> String {
+        format!("0x{}", hex::encode(self.encoded()))
+    }
+
+    /// The transaction hash, which identifies the transaction on the network.
+    pub fn hash(&self) -> Hash {
+        Hash(ic_crypto_sha3::Keccak256::hash(self.encoded()))
+    }
+}
+
+/// Outcome of a mined transaction, see <https://eips.ethereum.org/EIPS/eip-658>.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Encode, Decode)]
+pub enum TransactionStatus {
+    /// The transaction reverted, only its fee was paid.
+    #[n(0)]
+    Failure,
+    /// The transaction succeeded.
+    #[n(1)]
+    Success,
+}
+
+/// The parts of a transaction receipt the minter needs to settle a withdrawal.
+#[derive(Clone, Debug, Eq, PartialEq, Encode, Decode)]
+pub struct TransactionReceipt {
+    /// The hash of the block containing the transaction.
+    #[n(0)]
+    pub block_hash: Hash,
+    /// The number of the block containing the transaction.
+    #[n(1)]
+    pub block_number: BlockNumber,
+    /// The price per gas actually paid, i.e. the base fee plus the priority fee.
+    #[n(2)]
+    pub effective_gas_price: WeiPerGas,
+    /// The amount of gas used by the transaction.
+    #[n(3)]
+    pub gas_used: GasAmount,
+    #[n(4)]
+    pub status: TransactionStatus,
+    /// The hash of the mined transaction.
+    #[n(5)]
+    pub transaction_hash: Hash,
+}
+
+impl TransactionReceipt {
+    /// The fee actually paid for the transaction.
+    pub fn effective_transaction_fee(&self) -> Wei {
+        self.effective_gas_price
+            .transaction_cost(self.gas_used)
+            .expect("BUG: the effective transaction fee overflows")
+    }
+}
+
+fn append_u256(s: &mut RlpStream, value: ethnum::u256) {
+    let leading_empty_bytes = value.leading_zeros() as usize / 8;
+    s.append(&&value.to_be_bytes()[leading_empty_bytes..]);
+}
+
+/// Fee parameters for the next EIP-1559 transactions, derived from the fee history.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GasFeeEstimate {
+    /// The base fee per gas of the next block.
+    pub base_fee_per_gas: WeiPerGas,
+    pub max_priority_fee_per_gas: WeiPerGas,
+}
+
+impl GasFeeEstimate {
+    /// The maximum fee per gas leaves room for the base fee to double,
+    /// i.e. for about 6 consecutive full blocks, before the transaction is included.
+    pub fn estimate_max_fee_per_gas(&self) -> WeiPerGas {
+        self.base_fee_per_gas
+            .checked_mul(2_u8)
+            .and_then(|fee| fee.checked_add(self.max_priority_fee_per_gas))
+            .unwrap_or(WeiPerGas::MAX)
+    }
+}
+
+/// Estimates the fees of the next transactions from the fee history of the latest blocks:
+/// the base fee of the next block and, over those blocks, the median of the priority fee
+/// paid at the [`FEE_HISTORY_REWARD_PERCENTILE`]th percentile of each block, which is
+/// at least [`MIN_MAX_PRIORITY_FEE_PER_GAS`].
+pub fn estimate_gas_fee(fee_history: &FeeHistory) -> Result<GasFeeEstimate, String> {
+    let base_fee_per_gas = fee_history
+        .baseFeePerGas
+        .last()
+        .map(|fee| WeiPerGas::from(*fee))
+        .ok_or("baseFeePerGas should not be empty")?;
+    let mut rewards: Vec<u128> = fee_history.reward.iter().flatten().copied().collect();
+    if rewards.is_empty() {
+        return Err("reward should not be empty".to_string());
+    }
+    rewards.sort_unstable();
+    let median_reward = WeiPerGas::from(rewards[rewards.len() / 2]);
+    Ok(GasFeeEstimate {
+        base_fee_per_gas,
+        max_priority_fee_per_gas: max(median_reward, MIN_MAX_PRIORITY_FEE_PER_GAS),
+    })
+}
+
+/// Returns the cached gas fee estimate, refreshing it first if it is stale.
+pub async fn lazy_refresh_gas_fee_estimate() -> Option<GasFeeEstimate> {
+    let now = ic_cdk::api::time();
+    match read_state(|s| s.last_transaction_price_estimate) {
+        Some((timestamp, estimate))
+            if now.saturating_sub(timestamp) < GAS_FEE_ESTIMATE_MAX_AGE_NS =>
+        {
+            Some(estimate)
+        }
+        _ => refresh_gas_fee_estimate().await,
+    }
+}
+
+/// Fetches the fee history of the latest blocks and caches the resulting estimate.
+/// Returns the cached estimate, if any, when a refresh is already in progress.
+pub async fn refresh_gas_fee_estimate() -> Option<GasFeeEstimate> {
+    let _guard = match TimerGuard::new(TaskType::RefreshGasFeeEstimate) {
+        Ok(guard) => guard,
+        Err(_) => return read_state(|s| s.last_transaction_price_estimate.map(|(_, e)| e)),
+    };
+    let fee_history = match RpcClient
+        .fee_history(FeeHistoryArgs {
+            blockCount: FEE_HISTORY_BLOCK_COUNT,
+            newestBlock: BlockTag::Latest,
+            rewardPercentiles: Some(ByteBuf::from(vec![FEE_HISTORY_REWARD_PERCENTILE])),
+        })
+        .await
+    {
+        Ok(Some(fee_history)) => fee_history,
+        Ok(None) => {
+            log!(INFO, "[refresh_gas_fee_estimate]: empty fee history");
+            return None;
+        }
+        Err(e) => {
+            log!(
+                INFO,
+                "[refresh_gas_fee_estimate]: failed to get the fee history: {e:?}"
+            );
+            return None;
+        }
+    };
+    match estimate_gas_fee(&fee_history) {
+        Ok(estimate) => {
+            log!(
+                DEBUG,
+                "[refresh_gas_fee_estimate]: estimated {estimate:?} from {fee_history:?}"
+            );
+            mutate_state(|s| {
+                s.last_transaction_price_estimate = Some((ic_cdk::api::time(), estimate))
+            });
+            Some(estimate)
+        }
+        Err(e) => {
+            log!(
+                INFO,
+                "[refresh_gas_fee_estimate]: invalid fee history {fee_history:?}: {e}"
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // The vectors below are signed with the key of the first default Anvil/Hardhat
+    // development account 0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266.
+
+    fn native_transfer_on_amoy() -> SignedEip1559TransactionRequest {
+        SignedEip1559TransactionRequest {
+            transaction: Eip1559TransactionRequest {
+                chain_id: 80002,
+                nonce: TransactionNonce::ZERO,
+                max_priority_fee_per_gas: WeiPerGas::new(30_000_000_000),
+                max_fee_per_gas: WeiPerGas::new(60_000_000_000),
+                gas_limit: GasAmount::new(21_000),
+                destination: Address::from_str("0xbaf59b045c6b53bcc849e2a487c14f234435cc51")
+                    .unwrap(),
+                amount: Wei::new(1_000_000_000_000_000_000),
+                data: vec![],
+                access_list: AccessList::new(),
+            },
+            signature: Eip1559Signature {
+                signature_y_parity: true,
+                r: ethnum::u256::from_str_hex(
+                    "0x434026a60251305a45f73fb0b2394852253c25c70c557b9ca291bcbed57d9d1a",
+                )
+                .unwrap(),
+                s: ethnum::u256::from_str_hex(
+                    "0x6daaa6bf3ad3d604fb088e5699944d118852cee62adc68f5b2827f40ac775604",
+                )
+                .unwrap(),
+            },
+        }
+    }
+
+    fn erc20_transfer_with_access_list_on_mainnet() -> SignedEip1559TransactionRequest {
+        let wmatic = Address::from_str("0x0d500b1d8e8ef31e21c99d1db9a6444d3adf1270").unwrap();
+        SignedEip1559TransactionRequest {
+            transaction: Eip1559TransactionRequest {
+                chain_id: 137,
+                nonce: TransactionNonce::from(42_u8),
+                max_priority_fee_per_gas: WeiPerGas::new(30_000_000_000),
+                max_fee_per_gas: WeiPerGas::new(250_000_000_000),
+                gas_limit: GasAmount::new(65_000),
+                destination: wmatic,
+                amount: Wei::ZERO,
+                data: hex::decode(
+                    "a9059cbb000000000000000000000000baf59b045c6b53bcc849e2a487c14f234435cc51\
+                     0000000000000000000000000000000000000000000000000de0b6b3a7640000",
+                )
+                .unwrap(),
+                access_list: AccessList(vec![AccessListItem {
+                    address: wmatic,
+                    storage_keys: vec![StorageKey([0; 32]), {
+                        let mut key = [0; 32];
+                        key[31] = 1;
+                        StorageKey(key)
+                    }],
+                }]),
+            },
+            signature: Eip1559Signature {
+                signature_y_parity: false,
+                r: ethnum::u256::from_str_hex(
+                    "0x20e3b9e815d923ae92aed8dcdc86944a0b6112c61a29a6b7c2f6d79978d9cb62",
+                )
+                .unwrap(),
+                s: ethnum::u256::from_str_hex(
+                    "0x0a588ebcf158b67d739523ba8d1173219ddc07c192900632e5ff8b8dc51e4ba3",
+                )
+                .unwrap(),
+            },
+        }
+    }
+
+    #[test]
+    fn should_encode_unsigned_transaction() {
+        assert_eq!(
+            hex::encode(native_transfer_on_amoy().transaction.encoded()),
+            "02f483013882808506fc23ac00850df847580082520894baf59b045c6b53bcc849e2a487c14f234435cc51\
+             880de0b6b3a764000080c0"
+        );
+        assert_eq!(
+            hex::encode(
+                erc20_transfer_with_access_list_on_mainnet()
+                    .transaction
+                    .encoded()
+            ),
+            "02f8cb81892a8506fc23ac00853a3529440082fde8940d500b1d8e8ef31e21c99d1db9a6444d3adf1270\
+             80b844a9059cbb000000000000000000000000baf59b045c6b53bcc849e2a487c14f234435cc51\
+             0000000000000000000000000000000000000000000000000de0b6b3a7640000f85bf859940d500b1d\
+             8e8ef31e21c99d1db9a6444d3adf1270f842a0000000000000000000000000000000000000000000000000\
+             0000000000000000a00000000000000000000000000000000000000000000000000000000000000001"
+        );
+    }
+
+    #[test]
+    fn should_compute_hash_to_sign() {
+        assert_eq!(
+            native_transfer_on_amoy().transaction.hash(),
+            Hash::from_str("0xda81553f5cb6c3d67f76845ad5d664df613e133c4c127c76c6be9f05574f178e")
+                .unwrap()
+        );
+        assert_eq!(
+            erc20_transfer_with_access_list_on_mainnet()
+                .transaction
+                .hash(),
+            Hash::from_str("0x1643a42a9127ca368f86ac70dc2371ccc1561dff22b4c6a0655dcce6c821bacd")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn should_encode_signed_transaction() {
+        assert_eq!(
+            native_transfer_on_amoy().raw_transaction_hex(),
+            "0x02f87783013882808506fc23ac00850df847580082520894baf59b045c6b53bcc849e2a487c14f234435cc51\
+             880de0b6b3a764000080c001a0434026a60251305a45f73fb0b2394852253c25c70c557b9ca291bcbed57d\
+             9d1aa06daaa6bf3ad3d604fb088e5699944d118852cee62adc68f5b2827f40ac775604"
+        );
+        assert_eq!(
+            erc20_transfer_with_access_list_on_mainnet().raw_transaction_hex(),
+            "0x02f9010e81892a8506fc23ac00853a3529440082fde8940d500b1d8e8ef31e21c99d1db9a6444d3adf1270\
+             80b844a9059cbb000000000000000000000000baf59b045c6b53bcc849e2a487c14f234435cc51\
+             0000000000000000000000000000000000000000000000000de0b6b3a7640000f85bf859940d500b1d\
+             8e8ef31e21c99d1db9a6444d3adf1270f842a0000000000000000000000000000000000000000000000000\
+             0000000000000000a0000000000000000000000000000000000000000000000000000000000000000180a0\
+             20e3b9e815d923ae92aed8dcdc86944a0b6112c61a29a6b7c2f6d79978d9cb62a00a588ebcf158b67d73\
+             9523ba8d1173219ddc07c192900632e5ff8b8dc51e4ba3"
+        );
+    }
+
+    #[test]
+    fn should_compute_transaction_hash() {
+        assert_eq!(
+            native_transfer_on_amoy().hash(),
+            Hash::from_str("0x833cc3e1ce2f8c9e8249d7e538bdd6c9dba4245f3f7dbc5b3b7cc08d71765e40")
+                .unwrap()
+        );
+        assert_eq!(
+            erc20_transfer_with_access_list_on_mainnet().hash(),
+            Hash::from_str("0x242d5cc57e0fb75da5d940e30279169efa963922951fb08693cb718118576ace")
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,957 @@
+use candid::{Nat, Principal};
+use ic_canister_log::log;
+use icrc_ledger_client_cdk::{CdkRuntime, ICRC1Client};
+use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc1::transfer::TransferArg;
+use icrc_ledger_types::icrc2::transfer_from::TransferFromArgs;
+use num_traits::ToPrimitive;
+use std::cmp::{max, min};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::endpoints::{RetrieveErc20Request, RetrieveMaticRequest, WithdrawalError};
+use crate::evm_rpc_canister::{BlockTag, SendRawTransactionStatus};
+use crate::guard::{RetrieveMaticGuard, TimerGuard};
+use crate::log_types::address::Address;
+use crate::logs::{DEBUG, INFO};
+use crate::memo::{BurnMemo, MintMemo};
+use crate::numeric::{
+    CkTokenAmount, GasAmount, LedgerBurnIndex, LedgerMintIndex, TransactionCount, TransactionNonce,
+    Wei, WeiPerGas,
+};
+use crate::rpc_client::RpcClient;
+use crate::state::audit::process_event;
+use crate::state::event::EventType;
+use crate::state::transactions::{
+    Erc20WithdrawalRequest, MaticWithdrawalRequest, ReimbursementIndex, WithdrawalRequest,
+};
+use crate::state::{minter_address, mutate_state, read_state, TaskType};
+use crate::tx::{
+    lazy_refresh_gas_fee_estimate, AccessList, Eip1559TransactionRequest, GasFeeEstimate,
+    TransactionStatus,
+};
+
+/// Maximum number of withdrawal requests for which transactions are created in a single run.
+const WITHDRAWAL_REQUESTS_BATCH_SIZE: usize = 5;
+
+/// A withdrawal request for which no transaction could be created within this delay
+/// is abandoned and its burned icMATIC reimbursed.
+const WITHDRAWAL_REQUEST_TIMEOUT_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Gas limit of a plain transfer of the native currency.
+pub const NATIVE_TRANSFER_GAS_LIMIT: GasAmount = GasAmount::new(21_000);
+
+/// Gas limit of a call to the `transfer` function of an ERC-20 token contract.
+pub const ERC20_WITHDRAWAL_GAS_LIMIT: GasAmount = GasAmount::new(65_000);
+
+/// Selector of `transfer(address,uint256)`.
+const ERC20_TRANSFER_SELECTOR: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+
+/// Burns `amount` icMATIC from the caller's account and queues a request
+/// to send `amount` MATIC, minus the transaction fee, to `recipient`.
+///
+/// The caller must have approved the minter to spend `amount` icMATIC
+/// (plus the ledger fee) with `icrc2_approve`.
+pub async fn withdraw_matic(
+    amount: Nat,
+    recipient: String,
+) -> Result<RetrieveMaticRequest, WithdrawalError> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        ic_cdk::trap("anonymous principal is not allowed");
+    }
+    let _guard = RetrieveMaticGuard::new(caller).map_err(|e| {
+        WithdrawalError::TemporarilyUnavailable(format!(
+            "failed to acquire the withdrawal lock for {caller}: {e:?}"
+        ))
+    })?;
+
+    let destination = Address::from_str(&recipient)
+        .map_err(|e| WithdrawalError::InvalidDestination(format!("{recipient}: {e}")))?;
+    if destination == Address::ZERO {
+        return Err(WithdrawalError::InvalidDestination(
+            "cannot withdraw to the zero address".to_string(),
+        ));
+    }
+    let withdrawal_amount = Wei::try_from(amount.clone())
+        .unwrap_or_else(|e| ic_cdk::trap(&format!("invalid withdrawal amount {amount}: {e}")));
+    let (ledger_canister_id, token_symbol, minimum_withdrawal_amount) = read_state(|s| {
+        (
+            s.icmatic_ledger_id,
+            s.icmatic_token_symbol(),
+            s.icmatic_minimum_withdrawal_amount,
+        )
+    });
+    if withdrawal_amount < minimum_withdrawal_amount {
+        return Err(WithdrawalError::AmountTooLow {
+            min_withdrawal_amount: minimum_withdrawal_amount.into(),
+        });
+    }
+
+    let ledger_burn_index = burn(
+        ledger_canister_id,
+        &token_symbol,
+        caller,
+        amount,
+        BurnMemo::Convert {
+            to_address: destination,
+        },
+    )
+    .await?;
+    let request = MaticWithdrawalRequest {
+        withdrawal_amount,
+        destination,
+        ledger_burn_index,
+        from: caller,
+        created_at: ic_cdk::api::time(),
+    };
+    log!(
+        INFO,
+        "[withdraw_matic]: queuing withdrawal request {request:?}"
+    );
+    mutate_state(|s| process_event(s, EventType::AcceptedMaticWithdrawalRequest(request)));
+    ic_cdk_timers::set_timer(Duration::from_secs(0), || {
+        ic_cdk::spawn(process_retrieve_matic_requests())
+    });
+    Ok(RetrieveMaticRequest {
+        block_index: Nat::from(ledger_burn_index.get()),
+    })
+}
+
+/// Burns icMATIC for the maximum transaction fee, then `amount` icERC20 from the caller's
+/// account, and queues a request to transfer `amount` tokens from the minter's address
+/// to `recipient`.
+///
+/// The caller must have approved the minter to spend the icMATIC and the icERC20
+/// (plus the ledger fees) with `icrc2_approve`. The icMATIC are reimbursed if the
+/// icERC20 cannot be burned.
+pub async fn withdraw_erc20(
+    amount: Nat,
+    erc20_contract_address: String,
+    recipient: String,
+) -> Result<RetrieveErc20Request, WithdrawalError> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        ic_cdk::trap("anonymous principal is not allowed");
+    }
+    let _guard = RetrieveMaticGuard::new(caller).map_err(|e| {
+        WithdrawalError::TemporarilyUnavailable(format!(
+            "failed to acquire the withdrawal lock for {caller}: {e:?}"
+        ))
+    })?;
+
+    let destination = Address::from_str(&recipient)
+        .map_err(|e| WithdrawalError::InvalidDestination(format!("{recipient}: {e}")))?;
+    if destination == Address::ZERO {
+        return Err(WithdrawalError::InvalidDestination(
+            "cannot withdraw to the zero address".to_string(),
+        ));
+    }
+    let token = Address::from_str(&erc20_contract_address)
+        .ok()
+        .and_then(|address| read_state(|s| s.erc20_token(&address).cloned()))
+        .ok_or_else(|| WithdrawalError::TokenNotSupported(erc20_contract_address.clone()))?;
+    let withdrawal_amount = CkTokenAmount::try_from(amount.clone())
+        .unwrap_or_else(|e| ic_cdk::trap(&format!("invalid withdrawal amount {amount}: {e}")));
+    if withdrawal_amount == CkTokenAmount::ZERO {
+        return Err(WithdrawalError::AmountTooLow {
+            min_withdrawal_amount: CkTokenAmount::ONE.into(),
+        });
+    }
+    let max_transaction_fee = match lazy_refresh_gas_fee_estimate().await {
+        Some(estimate) => estimate
+            .estimate_max_fee_per_gas()
+            .transaction_cost(ERC20_WITHDRAWAL_GAS_LIMIT)
+            .unwrap_or(Wei::MAX),
+        None => {
+            return Err(WithdrawalError::TemporarilyUnavailable(
+                "no gas fee estimate".to_string(),
+            ))
+        }
+    };
+
+    let (icmatic_ledger_id, icmatic_token_symbol) =
+        read_state(|s| (s.icmatic_ledger_id, s.icmatic_token_symbol()));
+    let icmatic_ledger_burn_index = burn(
+        icmatic_ledger_id,
+        &icmatic_token_symbol,
+        caller,
+        max_transaction_fee.into(),
+        BurnMemo::Erc20GasFee {
+            erc20_contract_address: token.erc20_contract_address,
+            erc20_withdrawal_amount: withdrawal_amount,
+            to_address: destination,
+        },
+    )
+    .await?;
+    let erc20_ledger_burn_index = match burn(
+        token.ledger_id,
+        &token.symbol,
+        caller,
+        amount,
+        BurnMemo::Erc20Convert {
+            icmatic_withdrawal_id: icmatic_ledger_burn_index.get(),
+            to_address: destination,
+        },
+    )
+    .await
+    {
+        Ok(burn_index) => burn_index,
+        Err(error) => {
+            mutate_state(|s| {
+                process_event(
+                    s,
+                    EventType::FailedErc20WithdrawalRequest {
+                        withdrawal_id: icmatic_ledger_burn_index,
+                        reimbursed_amount: max_transaction_fee,
+                        to: caller,
+                    },
+                )
+            });
+            schedule_reimbursement();
+            return Err(error);
+        }
+    };
+
+    let request = Erc20WithdrawalRequest {
+        max_transaction_fee,
+        withdrawal_amount,
+        destination,
+        icmatic_ledger_burn_index,
+        erc20_contract_address: token.erc20_contract_address,
+        erc20_ledger_id: token.ledger_id,
+        erc20_ledger_burn_index,
+        from: caller,
+        created_at: ic_cdk::api::time(),
+    };
+    log!(
+        INFO,
+        "[withdraw_erc20]: queuing withdrawal request {request:?}"
+    );
+    mutate_state(|s| process_event(s, EventType::AcceptedErc20WithdrawalRequest(request)));
+    ic_cdk_timers::set_timer(Duration::from_secs(0), || {
+        ic_cdk::spawn(process_retrieve_matic_requests())
+    });
+    Ok(RetrieveErc20Request {
+        icmatic_block_index: Nat::from(icmatic_ledger_burn_index.get()),
+        erc20_block_index: Nat::from(erc20_ledger_burn_index.get()),
+    })
+}
+
+/// Burns `amount` tokens of `from` on the given ledger, by transferring them to the minter.
+async fn burn(
+    ledger_canister_id: Principal,
+    token_symbol: &str,
+    from: Principal,
+    amount: Nat,
+    memo: BurnMemo,
+) -> Result<LedgerBurnIndex, WithdrawalError> {
+    let client = ICRC1Client {
+        runtime: CdkRuntime,
+        ledger_canister_id,
+    };
+    log!(INFO, "[burn]: burning {amount} {token_symbol} from {from}");
+    match client
+        .transfer_from(TransferFromArgs {
+            spender_subaccount: None,
+            from: from.into(),
+            to: ic_cdk::id().into(),
+            amount,
+            fee: None,
+            memo: Some(memo.into()),
+            created_at_time: None,
+        })
+        .await
+    {
+        Ok(Ok(block_index)) => {
+            // The tokens are burned: the withdrawal must be accepted even if the
+            // block index is out of range.
+            let block_index = block_index.0.to_u64().unwrap_or_else(|| {
+                log!(
+                    INFO,
+                    "[ALERT] burned {token_symbol} from {from} in block {block_index} beyond u64"
+                );
+                u64::MAX
+            });
+            Ok(LedgerBurnIndex::new(block_index))
+        }
+        Ok(Err(error)) => {
+            log!(
+                INFO,
+                "[burn]: failed to burn {token_symbol} from {from}: {error:?}"
+            );
+            Err(WithdrawalError::from(error))
+        }
+        Err((code, message)) => Err(WithdrawalError::TemporarilyUnavailable(format!(
+            "failed to call the {token_symbol} ledger ({ledger_canister_id}): {code} {message}"
+        ))),
+    }
+}
+
+/// Creates, signs and sends the Polygon transactions fulfilling the withdrawal requests.
+///
+/// The transaction count of the minter's address at the `finalized` block is used to settle
+/// the withdrawals whose transaction was mined and to move the next nonce forward,
+/// and the one at the `latest` block to tell which transactions
+/// are still waiting to be mined, which are then resubmitted with bumped fees if needed.
+pub async fn process_retrieve_matic_requests() {
+    let _guard = match TimerGuard::new(TaskType::RetrieveEth) {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+    if read_state(|s| s.polygon_transactions.is_empty()) {
+        return;
+    }
+
+    let minter_address = match minter_address().await {
+        Ok(address) => address,
+        Err(e) => {
+            log!(
+                INFO,
+                "[process_retrieve_matic_requests]: failed to get the minter address: {e}"
+            );
+            return;
+        }
+    };
+    let finalized_transaction_count = match RpcClient
+        .get_transaction_count(minter_address, BlockTag::Finalized)
+        .await
+    {
+        Ok(count) => count,
+        Err(e) => {
+            log!(
+                INFO,
+                "[process_retrieve_matic_requests]: failed to get the finalized transaction count: {e:?}"
+            );
+            return;
+        }
+    };
+    let latest_transaction_count = match RpcClient
+        .get_transaction_count(minter_address, BlockTag::Latest)
+        .await
+    {
+        Ok(count) => count,
+        Err(e) => {
+            log!(
+                INFO,
+                "[process_retrieve_matic_requests]: failed to get the latest transaction count: {e:?}"
+            );
+            return;
+        }
+    };
+
+    finalize_transactions_batch(finalized_transaction_count).await;
+    sync_transaction_nonce(finalized_transaction_count);
+    resubmit_transactions_batch(latest_transaction_count).await;
+    create_transactions_batch().await;
+    sign_transactions_batch().await;
+    send_transactions_batch(latest_transaction_count).await;
+}
+
+/// Fetches the receipts of the withdrawals whose nonce was used in a finalized block.
+/// Since any of the transactions of a withdrawal may have been mined, the most recent
+/// ones are tried first.
+async fn finalize_transactions_batch(finalized_transaction_count: TransactionCount) {
+    let transactions = read_state(|s| {
+        s.polygon_transactions
+            .transactions_to_finalize(finalized_transaction_count)
+    });
+    for (withdrawal_id, sent_transactions) in transactions {
+        let mut receipt = None;
+        for transaction in sent_transactions.iter().rev() {
+            match RpcClient.get_transaction_receipt(transaction.hash()).await {
+                Ok(Some(transaction_receipt)) => {
+                    receipt = Some(transaction_receipt);
+                    break;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    log!(
+                        INFO,
+                        "[finalize_transactions_batch]: failed to get the receipt of transaction {} for withdrawal {withdrawal_id}: {e:?}",
+                        transaction.hash()
+                    );
+                    break;
+                }
+            }
+        }
+        match receipt {
+            Some(transaction_receipt) => {
+                log!(
+                    INFO,
+                    "[finalize_transactions_batch]: withdrawal {withdrawal_id} finalized with receipt {transaction_receipt:?}"
+                );
+                if transaction_receipt.status == TransactionStatus::Failure {
+                    schedule_reimbursement();
+                }
+                mutate_state(|s| {
+                    process_event(
+                        s,
+                        EventType::FinalizedTransaction {
+                            withdrawal_id,
+                            transaction_receipt,
+                        },
+                    )
+                });
+            }
+            None => log!(
+                INFO,
+                "[finalize_transactions_batch]: no receipt yet for any transaction of withdrawal {withdrawal_id}"
+            ),
+        }
+    }
+}
+
+fn sync_transaction_nonce(finalized_transaction_count: TransactionCount) {
+    let finalized_nonce: TransactionNonce = finalized_transaction_count.change_units();
+    let should_sync = read_state(|s| {
+        s.polygon_transactions.has_no_transaction_in_flight()
+            && finalized_nonce > s.polygon_transactions.next_nonce()
+    });
+    if should_sync {
+        log!(
+            INFO,
+            "[sync_transaction_nonce]: moving the next nonce forward to {finalized_nonce}"
+        );
+        mutate_state(|s| {
+            process_event(
+                s,
+                EventType::SyncedTransactionNonce {
+                    next_nonce: finalized_nonce,
+                },
+            )
+        });
+    }
+}
+
+async fn resubmit_transactions_batch(latest_transaction_count: TransactionCount) {
+    let transactions = read_state(|s| {
+        s.polygon_transactions
+            .transactions_to_resubmit(latest_transaction_count)
+    });
+    if transactions.is_empty() {
+        return;
+    }
+    let gas_fee_estimate = match lazy_refresh_gas_fee_estimate().await {
+        Some(estimate) => estimate,
+        None => {
+            log!(
+                INFO,
+                "[resubmit_transactions_batch]: skipping resubmission: no gas fee estimate"
+            );
+            return;
+        }
+    };
+    for (request, transaction) in transactions {
+        match resubmit_transaction(&request, &transaction, gas_fee_estimate) {
+            Ok(Some(new_transaction)) => {
+                log!(
+                    INFO,
+                    "[resubmit_transactions_batch]: replacing transaction {transaction:?} with {new_transaction:?}"
+                );
+                mutate_state(|s| {
+                    process_event(
+                        s,
+                        EventType::ReplacedTransaction {
+                            withdrawal_id: request.withdrawal_id(),
+                            transaction: new_transaction,
+                        },
+                    )
+                });
+            }
+            Ok(None) => {}
+            Err(e) => {
+                log!(
+                    INFO,
+                    "[resubmit_transactions_batch]: failed to resubmit transaction for {request:?}: {e}"
+                );
+            }
+        }
+    }
+}
+
+/// Nodes replace a pending transaction only if both of its fees increase by at least 10%.
+fn bump_fee(fee: WeiPerGas) -> WeiPerGas {
+    fee.checked_mul(11_u8)
+        .and_then(|fee| fee.checked_div_ceil(10_u8))
+        .unwrap_or(WeiPerGas::MAX)
+}
+
+/// Returns a transaction replacing `transaction` if its fees are lower than the current estimate.
+///
+/// The fee of an ERC-20 withdrawal was burned upfront, so the fees of its transaction are
+/// capped to it. Once the cap leaves no room for the minimum bump, the transaction is kept
+/// as is until the fees drop enough for it to be mined.
+fn resubmit_transaction(
+    request: &WithdrawalRequest,
+    transaction: &Eip1559TransactionRequest,
+    gas_fee_estimate: GasFeeEstimate,
+) -> Result<Option<Eip1559TransactionRequest>, String> {
+    let estimated_max_fee_per_gas = gas_fee_estimate.estimate_max_fee_per_gas();
+    if estimated_max_fee_per_gas <= transaction.max_fee_per_gas
+        && gas_fee_estimate.max_priority_fee_per_gas <= transaction.max_priority_fee_per_gas
+    {
+        return Ok(None);
+    }
+    let mut max_fee_per_gas = max(
+        estimated_max_fee_per_gas,
+        bump_fee(transaction.max_fee_per_gas),
+    );
+    let mut max_priority_fee_per_gas = max(
+        gas_fee_estimate.max_priority_fee_per_gas,
+        bump_fee(transaction.max_priority_fee_per_gas),
+    );
+    if let WithdrawalRequest::Erc20(request) = request {
+        let max_fee_per_gas_cap = request
+            .max_transaction_fee
+            .fee_per_gas(transaction.gas_limit)
+            .unwrap_or(WeiPerGas::ZERO);
+        max_fee_per_gas = min(max_fee_per_gas, max_fee_per_gas_cap);
+        max_priority_fee_per_gas = min(max_priority_fee_per_gas, max_fee_per_gas);
+        if max_fee_per_gas < bump_fee(transaction.max_fee_per_gas)
+            || max_priority_fee_per_gas < bump_fee(transaction.max_priority_fee_per_gas)
+        {
+            return Ok(None);
+        }
+    }
+    let mut new_transaction = Eip1559TransactionRequest {
+        max_priority_fee_per_gas,
+        max_fee_per_gas,
+        ..transaction.clone()
+    };
+    new_transaction.amount = transaction_amount(request, &new_transaction)?;
+    Ok(Some(new_transaction))
+}
+
+async fn create_transactions_batch() {
+    let (chain_id, requests) = read_state(|s| {
+        (
+            s.evm_network.chain_id,
+            s.polygon_transactions
+                .withdrawal_requests_batch(WITHDRAWAL_REQUESTS_BATCH_SIZE),
+        )
+    });
+    if requests.is_empty() {
+        return;
+    }
+    let gas_fee_estimate = match lazy_refresh_gas_fee_estimate().await {
+        Some(estimate) => estimate,
+        None => {
+            log!(
+                INFO,
+                "[create_transactions_batch]: skipping transaction creation: no gas fee estimate"
+            );
+            return;
+        }
+    };
+    for request in requests {
+        let nonce = read_state(|s| s.polygon_transactions.next_nonce());
+        match create_transaction(&request, nonce, chain_id, gas_fee_estimate) {
+            Ok(transaction) => {
+                log!(
+                    DEBUG,
+                    "[create_transactions_batch]: created transaction {transaction:?} for withdrawal request {request:?}"
+                );
+                mutate_state(|s| {
+                    process_event(
+                        s,
+                        EventType::CreatedTransaction {
+                            withdrawal_id: request.withdrawal_id(),
+                            transaction,
+                        },
+                    )
+                });
+            }
+            Err(e) => {
+                log!(
+                    INFO,
+                    "[create_transactions_batch]: failed to create transaction for {request:?}: {e}"
+                );
+                if ic_cdk::api::time().saturating_sub(request.created_at())
+                    > WITHDRAWAL_REQUEST_TIMEOUT_NS
+                {
+                    log!(
+                        INFO,
+                        "[create_transactions_batch]: abandoning withdrawal request {request:?}"
+                    );
+                    mutate_state(|s| {
+                        process_event(
+                            s,
+                            EventType::AbandonedWithdrawalRequest {
+                                withdrawal_id: request.withdrawal_id(),
+                                reason: e,
+                            },
+                        )
+                    });
+                    schedule_reimbursement();
+                }
+            }
+        }
+    }
+}
+
+/// Creates the transaction of a withdrawal request: a plain transfer for MATIC,
+/// a call to the token's `transfer` for ERC-20 tokens. `TokenLock.lockTokens` forwards
+/// the deposited tokens to its `minter`, so the minter's address holds them.
+fn create_transaction(
+    request: &WithdrawalRequest,
+    nonce: TransactionNonce,
+    chain_id: u64,
+    gas_fee_estimate: GasFeeEstimate,
+) -> Result<Eip1559TransactionRequest, String> {
+    let (gas_limit, destination, data) = match request {
+        WithdrawalRequest::Matic(request) => {
+            (NATIVE_TRANSFER_GAS_LIMIT, request.destination, Vec::new())
+        }
+        WithdrawalRequest::Erc20(request) => (
+            ERC20_WITHDRAWAL_GAS_LIMIT,
+            request.erc20_contract_address,
+            erc20_transfer_call_data(request),
+        ),
+    };
+    let mut transaction = Eip1559TransactionRequest {
+        chain_id,
+        nonce,
+        max_priority_fee_per_gas: gas_fee_estimate.max_priority_fee_per_gas,
+        max_fee_per_gas: gas_fee_estimate.estimate_max_fee_per_gas(),
+        gas_limit,
+        destination,
+        amount: Wei::ZERO,
+        data,
+        access_list: AccessList::new(),
+    };
+    transaction.amount = transaction_amount(request, &transaction)?;
+    Ok(transaction)
+}
+
+/// The MATIC sent by the transaction of a withdrawal request: the withdrawal amount minus
+/// the maximum transaction fee for MATIC, nothing for ERC-20 tokens whose fee was burned
+/// upfront and must cover the maximum transaction fee.
+fn transaction_amount(
+    request: &WithdrawalRequest,
+    transaction: &Eip1559TransactionRequest,
+) -> Result<Wei, String> {
+    let max_transaction_fee = transaction.max_transaction_fee();
+    match request {
+        WithdrawalRequest::Matic(request) => request
+            .withdrawal_amount
+            .checked_sub(max_transaction_fee)
+            .ok_or_else(|| {
+                format!(
+                    "withdrawal amount {} does not cover the maximum transaction fee {}",
+                    request.withdrawal_amount, max_transaction_fee
+                )
+            }),
+        WithdrawalRequest::Erc20(request) => {
+            if max_transaction_fee > request.max_transaction_fee {
+                return Err(format!(
+                    "burned fee {} does not cover the maximum transaction fee {}",
+                    request.max_transaction_fee, max_transaction_fee
+                ));
+            }
+            Ok(Wei::ZERO)
+        }
+    }
+}
+
+/// ABI-encodes the call `transfer(destination, amount)`.
+fn erc20_transfer_call_data(request: &Erc20WithdrawalRequest) -> Vec<u8> {
+    let mut data = ERC20_TRANSFER_SELECTOR.to_vec();
+    data.extend_from_slice(&<[u8; 32]>::from(&request.destination));
+    data.extend_from_slice(&request.withdrawal_amount.to_be_bytes());
+    data
+}
+
+async fn sign_transactions_batch() {
+    let transactions = read_state(|s| s.polygon_transactions.transactions_to_sign());
+    for (withdrawal_id, transaction) in transactions {
+        match transaction.sign().await {
+            Ok(signed_transaction) => {
+                log!(
+                    DEBUG,
+                    "[sign_transactions_batch]: signed transaction {} for withdrawal {withdrawal_id}",
+                    signed_transaction.hash()
+                );
+                mutate_state(|s| {
+                    process_event(
+                        s,
+                        EventType::SignedTransaction {
+                            withdrawal_id,
+                            transaction: signed_transaction,
+                        },
+                    )
+                });
+            }
+            Err(e) => {
+                log!(
+                    INFO,
+                    "[sign_transactions_batch]: failed to sign transaction for withdrawal {withdrawal_id}: {e}"
+                );
+            }
+        }
+    }
+}
+
+async fn send_transactions_batch(latest_transaction_count: TransactionCount) {
+    let transactions = read_state(|s| {
+        s.polygon_transactions
+            .transactions_to_send(latest_transaction_count)
+    });
+    for (withdrawal_id, transaction) in transactions {
+        match RpcClient
+            .send_raw_transaction(transaction.raw_transaction_hex())
+            .await
+        {
+            Ok(status) => {
+                log!(
+                    DEBUG,
+                    "[send_transactions_batch]: sent transaction {} for withdrawal {withdrawal_id}: {status:?}",
+                    transaction.hash()
+                );
+                let transaction_hash = transaction.hash();
+                // Providers that already received the transaction report it with `NonceTooLow`.
+                if let SendRawTransactionStatus::Ok(_) | SendRawTransactionStatus::NonceTooLow =
+                    status
+                {
+                    mutate_state(|s| {
+                        // Signed transactions are sent again until they are mined.
+                        if s.polygon_transactions
+                            .sent_transaction_hash(&withdrawal_id)
+                            != Some(&transaction_hash)
+                        {
+                            process_event(
+                                s,
+                                EventType::SentTransaction {
+                                    withdrawal_id,
+                                    transaction_hash,
+                                },
+                            )
+                        }
+                    });
+                }
+            }
+            Err(e) => log!(
+                INFO,
+                "[send_transactions_batch]: failed to send transaction {} for withdrawal {withdrawal_id}: {e:?}",
+                transaction.hash()
+            ),
+        }
+    }
+}
+
+fn schedule_reimbursement() {
+    ic_cdk_timers::set_timer(Duration::from_secs(0), || {
+        ic_cdk::spawn(process_reimbursement())
+    });
+}
+
+/// Mints back the icMATIC of the withdrawals that failed or could not be sent.
+pub async fn process_reimbursement() {
+    let _guard = match TimerGuard::new(TaskType::Reimbursement) {
+        Ok(guard) => guard,
+        Err(_) => return,
+    };
+
+    let (icmatic_ledger_id, requests) = read_state(|s| {
+        (
+            s.icmatic_ledger_id,
+            s.polygon_transactions.reimbursement_requests(),
+        )
+    });
+
+    for (index, request) in requests {
+        let withdrawal_id = request.withdrawal_id;
+        let ledger_canister_id = match index {
+            ReimbursementIndex::IcMatic { .. } => icmatic_ledger_id,
+            ReimbursementIndex::IcErc20 { ledger_id, .. } => ledger_id,
+        };
+        let client = ICRC1Client {
+            runtime: CdkRuntime,
+            ledger_canister_id,
+        };
+        // Ensure that even if we were to panic in the callback, after having contacted the ledger to mint the tokens,
+        // this reimbursement will not be processed again.
+        let prevent_double_minting_guard = QuarantineReimbursementOnDrop::new(index);
+        let block_index = match client
+            .transfer(TransferArg {
+                from_subaccount: None,
+                to: Account {
+                    owner: request.to,
+                    subaccount: None,
+                },
+                fee: None,
+                created_at_time: None,
+                memo: Some(MintMemo::from(&request).into()),
+                amount: Nat::from(request.reimbursed_amount),
+            })
+            .await
+        {
+            // The tokens are minted: the reimbursement must be recorded as such even if the
+            // block index is out of range.
+            Ok(Ok(block_index)) => block_index.0.to_u64().unwrap_or_else(|| {
+                log!(
+                    INFO,
+                    "[ALERT] reimbursed {request:?} in block {block_index} beyond u64"
+                );
+                u64::MAX
+            }),
+            Ok(Err(err)) => {
+                log!(INFO, "Failed to reimburse withdrawal {request:?}: {err}");
+                prevent_double_minting_guard.disarm();
+                continue;
+            }
+            Err(err) => {
+                log!(
+                    INFO,
+                    "Failed to send a message to the ledger ({ledger_canister_id}): {err:?}"
+                );
+                prevent_double_minting_guard.disarm();
+                continue;
+            }
+        };
+        let reimbursed_in_block = LedgerMintIndex::new(block_index);
+        let event = match index {
+            ReimbursementIndex::IcMatic { withdrawal_id } => EventType::ReimbursedWithdrawal {
+                withdrawal_id,
+                reimbursed_in_block,
+            },
+            ReimbursementIndex::IcErc20 {
+                withdrawal_id,
+                ledger_id,
+            } => EventType::ReimbursedErc20Withdrawal {
+                withdrawal_id,
+                ledger_id,
+                reimbursed_in_block,
+            },
+        };
+        mutate_state(|s| process_event(s, event));
+        log!(
+            INFO,
+            "Reimbursed {} tokens of ledger {ledger_canister_id} to {} for withdrawal {withdrawal_id} in block {block_index}",
+            request.reimbursed_amount,
+            request.to
+        );
+        prevent_double_minting_guard.disarm();
+    }
+}
+
+/// Quarantines the reimbursement when dropped, unless disarmed.
+/// Destructors of pending futures run when the canister traps in a callback.
+struct QuarantineReimbursementOnDrop {
+    index: Option<ReimbursementIndex>,
+}
+
+impl QuarantineReimbursementOnDrop {
+    fn new(index: ReimbursementIndex) -> Self {
+        Self { index: Some(index) }
+    }
+
+    fn disarm(mut self) {
+        self.index = None;
+    }
+}
+
+impl Drop for QuarantineReimbursementOnDrop {
+    fn drop(&mut self) {
+        let event = match self.index.take() {
+            Some(ReimbursementIndex::IcMatic { withdrawal_id }) => {
+                EventType::QuarantinedReimbursement { withdrawal_id }
+            }
+            Some(ReimbursementIndex::IcErc20 {
+                withdrawal_id,
+                ledger_id,
+            }) => EventType::QuarantinedErc20Reimbursement {
+                withdrawal_id,
+                ledger_id,
+            },
+            None => return,
+        };
+        mutate_state(|s| process_event(s, event));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A withdrawal of 1 WMATIC whose burned fee covers the gas limit at 60 gwei.
+    fn erc20_withdrawal_request() -> WithdrawalRequest {
+        WithdrawalRequest::Erc20(Erc20WithdrawalRequest {
+            max_transaction_fee: Wei::new(65_000 * 60_000_000_000),
+            withdrawal_amount: CkTokenAmount::new(1_000_000_000_000_000_000),
+            destination: Address::from_str("0xbaf59b045c6b53bcc849e2a487c14f234435cc51").unwrap(),
+            icmatic_ledger_burn_index: LedgerBurnIndex::new(7),
+            erc20_contract_address: Address::from_str("0x0d500b1d8e8ef31e21c99d1db9a6444d3adf1270")
+                .unwrap(),
+            erc20_ledger_id: Principal::from_text("ss2fx-dyaaa-aaaar-qacoq-cai").unwrap(),
+            erc20_ledger_burn_index: LedgerBurnIndex::new(3),
+            from: Principal::from_text("2chl6-4hpzw-vqaaa-aaaaa-c").unwrap(),
+            created_at: 0,
+        })
+    }
+
+    #[test]
+    fn should_transfer_erc20_tokens_from_minter_address() {
+        let wmatic = Address::from_str("0x0d500b1d8e8ef31e21c99d1db9a6444d3adf1270").unwrap();
+        let request = erc20_withdrawal_request();
+        let gas_fee_estimate = GasFeeEstimate {
+            base_fee_per_gas: WeiPerGas::new(15_000_000_000),
+            max_priority_fee_per_gas: WeiPerGas::new(30_000_000_000),
+        };
+
+        let transaction = create_transaction(
+            &request,
+            TransactionNonce::from(42_u8),
+            137,
+            gas_fee_estimate,
+        )
+        .unwrap();
+
+        assert_eq!(transaction.destination, wmatic);
+        assert_eq!(transaction.amount, Wei::ZERO);
+        assert_eq!(transaction.gas_limit, ERC20_WITHDRAWAL_GAS_LIMIT);
+        assert_eq!(
+            hex::encode(&transaction.data),
+            "a9059cbb000000000000000000000000baf59b045c6b53bcc849e2a487c14f234435cc51\
+             0000000000000000000000000000000000000000000000000de0b6b3a7640000"
+        );
+    }
+
+    #[test]
+    fn should_cap_erc20_resubmission_fees_to_burned_fee() {
+        let request = erc20_withdrawal_request();
+        let transaction = Eip1559TransactionRequest {
+            chain_id: 137,
+            nonce: TransactionNonce::from(42_u8),
+            max_priority_fee_per_gas: WeiPerGas::new(30_000_000_000),
+            max_fee_per_gas: WeiPerGas::new(50_000_000_000),
+            gas_limit: ERC20_WITHDRAWAL_GAS_LIMIT,
+            destination: Address::from_str("0x0d500b1d8e8ef31e21c99d1db9a6444d3adf1270").unwrap(),
+            amount: Wei::ZERO,
+            data: Vec::new(),
+            access_list: AccessList::new(),
+        };
+        let gas_fee_estimate = GasFeeEstimate {
+            base_fee_per_gas: WeiPerGas::new(40_000_000_000),
+            max_priority_fee_per_gas: WeiPerGas::new(30_000_000_000),
+        };
+
+        let resubmitted = resubmit_transaction(&request, &transaction, gas_fee_estimate)
+            .unwrap()
+            .expect("the burned fee leaves room for a bump");
+
+        assert_eq!(resubmitted.max_fee_per_gas, WeiPerGas::new(60_000_000_000));
+        assert_eq!(
+            resubmitted.max_priority_fee_per_gas,
+            WeiPerGas::new(33_000_000_000)
+        );
+        assert_eq!(
+            resubmitted.max_transaction_fee(),
+            Wei::new(65_000 * 60_000_000_000)
+        );
+        assert_eq!(
+            resubmit_transaction(&request, &resubmitted, gas_fee_estimate),
+            Ok(None)
+        );
+    }
+}